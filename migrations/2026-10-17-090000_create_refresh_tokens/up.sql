@@ -0,0 +1,17 @@
+-- Your SQL goes here
+CREATE TABLE refresh_tokens
+(
+    token_id        uuid                        NOT NULL PRIMARY KEY,
+    user_id         uuid                        NOT NULL REFERENCES users(user_id),
+    -- All tokens rotated from same login share family_id so that whole chain can be revoked at once.
+    family_id       uuid                        NOT NULL,
+    -- Hex encoded SHA-256 of the opaque token. Token itself is never stored.
+    token_hash      varchar(64)                 NOT NULL UNIQUE,
+    expires_at      timestamp with time zone    NOT NULL,
+    -- Set when token is exchanged for new one. Presenting it again means it was stolen.
+    used_at         timestamp with time zone,
+    revoked_at      timestamp with time zone,
+    created_at      timestamp with time zone    NOT NULL
+);
+
+CREATE INDEX refresh_tokens_family_id_index ON refresh_tokens (family_id);
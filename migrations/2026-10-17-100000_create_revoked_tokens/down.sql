@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE revoked_tokens;
+ALTER TABLE users DROP COLUMN token_generation;
\ No newline at end of file
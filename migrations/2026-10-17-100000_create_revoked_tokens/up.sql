@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Incremented to invalidate every token issued to the user so far ("log out all sessions").
+ALTER TABLE users ADD COLUMN token_generation integer NOT NULL DEFAULT 0;
+
+-- Access tokens logged out before their expiry. Rows can be deleted once expires_at has passed.
+CREATE TABLE revoked_tokens
+(
+    jti             uuid                        NOT NULL PRIMARY KEY,
+    user_id         uuid                        NOT NULL REFERENCES users(user_id),
+    expires_at      timestamp with time zone    NOT NULL,
+    revoked_at      timestamp with time zone    NOT NULL
+);
+
+CREATE INDEX revoked_tokens_revoked_at_index ON revoked_tokens (revoked_at);
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Responses of POST /api/v1/orders requests sent with Idempotency-Key header, replayed when client retries.
+CREATE TABLE idempotency_keys
+(
+    user_id             uuid                        NOT NULL REFERENCES users(user_id),
+    idempotency_key     varchar(255)                NOT NULL,
+    -- Hex encoded SHA-256 of request body, to detect key reuse with different payload.
+    request_hash        varchar(64)                 NOT NULL,
+    order_id            uuid                        NOT NULL REFERENCES orders(order_id),
+    response_status     integer                     NOT NULL,
+    response_body       text                        NOT NULL,
+    created_at          timestamp with time zone    NOT NULL,
+    PRIMARY KEY (user_id, idempotency_key)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE order_status_changes;
+ALTER TABLE orders DROP COLUMN status;
\ No newline at end of file
@@ -0,0 +1,20 @@
+-- Your SQL goes here
+-- Allowed transitions between statuses are enforced in application (see order_actions.rs).
+ALTER TABLE orders ADD COLUMN status varchar(20) NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'paid', 'fulfilled', 'shipped', 'delivered', 'cancelled', 'refunded'));
+
+-- One row per status transition. Initial 'pending' status on creation has NULL from_status.
+CREATE TABLE order_status_changes
+(
+    change_id       uuid                        NOT NULL PRIMARY KEY,
+    order_id        uuid                        NOT NULL REFERENCES orders(order_id),
+    from_status     varchar(20),
+    to_status       varchar(20)                 NOT NULL,
+    changed_at      timestamp with time zone    NOT NULL
+);
+
+CREATE INDEX order_status_changes_order_id_index ON order_status_changes (order_id);
+
+-- Existing orders get their creation recorded as initial transition.
+INSERT INTO order_status_changes (change_id, order_id, from_status, to_status, changed_at)
+SELECT md5(random()::text || order_id::text)::uuid, order_id, NULL, 'pending', created_at FROM orders;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- There is no endpoint to grant admin role, set it directly in db.
+ALTER TABLE users ADD COLUMN is_admin boolean NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE orders DROP COLUMN cancelled_at;
+ALTER TABLE orders DROP COLUMN cancellation_reason;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE orders ADD COLUMN cancellation_reason varchar(500);
+ALTER TABLE orders ADD COLUMN cancelled_at timestamp with time zone;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Incremented on every change of user, used to detect concurrent edits.
+ALTER TABLE users ADD COLUMN version integer NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX orders_user_id_created_at_idx;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+-- Serves keyset pagination of order list by creation time.
+CREATE INDEX orders_user_id_created_at_idx ON orders (user_id, created_at, order_id);
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE order_items DROP COLUMN currency;
+
+ALTER TABLE order_items ADD COLUMN price integer;
+UPDATE order_items SET price = (price_minor / 100)::integer;
+ALTER TABLE order_items ALTER COLUMN price SET NOT NULL;
+ALTER TABLE order_items DROP COLUMN price_minor;
+
+ALTER TABLE orders DROP COLUMN currency;
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Prices used to be whole currency units in integer column without currency. They are now bigint count of minor
+-- units (e.g. cents) along with ISO 4217 currency code. Existing rows are taken to be in USD.
+ALTER TABLE orders ADD COLUMN currency varchar(3) NOT NULL DEFAULT 'USD';
+ALTER TABLE orders ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE order_items ADD COLUMN price_minor bigint;
+UPDATE order_items SET price_minor = price::bigint * 100;
+ALTER TABLE order_items ALTER COLUMN price_minor SET NOT NULL;
+ALTER TABLE order_items ADD CONSTRAINT order_items_price_minor_check CHECK (price_minor >= 0);
+ALTER TABLE order_items DROP COLUMN price;
+
+ALTER TABLE order_items ADD COLUMN currency varchar(3) NOT NULL DEFAULT 'USD';
+ALTER TABLE order_items ALTER COLUMN currency DROP DEFAULT;
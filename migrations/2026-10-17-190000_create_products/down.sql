@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE order_items DROP COLUMN product_id;
+
+DROP TABLE products;
\ No newline at end of file
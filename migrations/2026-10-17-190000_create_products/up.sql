@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TABLE products
+(
+    product_id      uuid                        NOT NULL PRIMARY KEY,
+    sku             varchar(64)                 NOT NULL UNIQUE,
+    name            varchar(255)                NOT NULL,
+    price_minor     bigint                      NOT NULL CHECK (price_minor >= 0),
+    currency        varchar(3)                  NOT NULL,
+    active          boolean                     NOT NULL DEFAULT true,
+    created_at      timestamp with time zone    NOT NULL,
+    updated_at      timestamp with time zone    NOT NULL
+);
+
+-- Items created before product catalog existed have no product.
+ALTER TABLE order_items ADD COLUMN product_id uuid REFERENCES products(product_id);
+CREATE INDEX order_items_product_id_index ON order_items (product_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE stock_adjustments;
+
+ALTER TABLE products DROP COLUMN stock_available;
\ No newline at end of file
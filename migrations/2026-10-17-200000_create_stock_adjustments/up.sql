@@ -0,0 +1,19 @@
+-- Your SQL goes here
+-- Quantity which can still be ordered. Orders take stock when placed and give it back when cancelled.
+ALTER TABLE products ADD COLUMN stock_available integer NOT NULL DEFAULT 0 CHECK (stock_available >= 0);
+
+-- Audit trail of every change of stock_available.
+CREATE TABLE stock_adjustments
+(
+    adjustment_id   uuid                        NOT NULL PRIMARY KEY,
+    product_id      uuid                        NOT NULL REFERENCES products(product_id),
+    delta           integer                     NOT NULL,
+    stock_after     integer                     NOT NULL,
+    kind            varchar(20)                 NOT NULL,
+    order_id        uuid                        REFERENCES orders(order_id),
+    user_id         uuid                        NOT NULL REFERENCES users(user_id),
+    reason          varchar(500),
+    created_at      timestamp with time zone    NOT NULL
+);
+
+CREATE INDEX stock_adjustments_product_id_index ON stock_adjustments (product_id, created_at);
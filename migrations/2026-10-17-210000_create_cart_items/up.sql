@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- Cart of user is just the set of its rows here. Price is not stored, cart always shows current product price.
+CREATE TABLE cart_items
+(
+    user_id         uuid                        NOT NULL REFERENCES users(user_id),
+    product_id      uuid                        NOT NULL REFERENCES products(product_id),
+    qty             integer                     NOT NULL CHECK (qty > 0),
+    added_at        timestamp with time zone    NOT NULL,
+    updated_at      timestamp with time zone    NOT NULL,
+    PRIMARY KEY (user_id, product_id)
+);
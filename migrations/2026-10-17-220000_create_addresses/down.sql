@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE order_addresses;
+DROP TABLE addresses;
\ No newline at end of file
@@ -0,0 +1,36 @@
+-- Your SQL goes here
+-- Address book of users.
+CREATE TABLE addresses
+(
+    address_id      uuid                        NOT NULL PRIMARY KEY,
+    user_id         uuid                        NOT NULL REFERENCES users(user_id),
+    label           varchar(100),
+    full_name       varchar(255)                NOT NULL,
+    line1           varchar(255)                NOT NULL,
+    line2           varchar(255),
+    city            varchar(255)                NOT NULL,
+    region          varchar(255),
+    postal_code     varchar(20)                 NOT NULL,
+    country         varchar(2)                  NOT NULL,
+    phone           varchar(32),
+    created_at      timestamp with time zone    NOT NULL,
+    updated_at      timestamp with time zone    NOT NULL
+);
+CREATE INDEX addresses_user_id_index ON addresses (user_id);
+
+-- Copy of address taken when order is placed. There is no reference to addresses, so that editing or deleting
+-- address in address book does not change orders already placed with it.
+CREATE TABLE order_addresses
+(
+    order_id        uuid                        NOT NULL REFERENCES orders(order_id),
+    kind            varchar(16)                 NOT NULL CHECK (kind IN ('shipping', 'billing')),
+    full_name       varchar(255)                NOT NULL,
+    line1           varchar(255)                NOT NULL,
+    line2           varchar(255),
+    city            varchar(255)                NOT NULL,
+    region          varchar(255),
+    postal_code     varchar(20)                 NOT NULL,
+    country         varchar(2)                  NOT NULL,
+    phone           varchar(32),
+    PRIMARY KEY (order_id, kind)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE order_discounts;
+DROP TABLE coupons;
\ No newline at end of file
@@ -0,0 +1,37 @@
+-- Your SQL goes here
+-- Codes are stored in upper case and matched case insensitively. Fixed amount and minimum order total are in
+-- 'currency'; coupon with currency only applies to orders in that currency.
+CREATE TABLE coupons
+(
+    coupon_id               uuid                        NOT NULL PRIMARY KEY,
+    code                    varchar(64)                 NOT NULL UNIQUE,
+    kind                    varchar(16)                 NOT NULL CHECK (kind IN ('percentage', 'fixed')),
+    percent_off             integer                     CHECK (percent_off BETWEEN 1 AND 100),
+    amount_off_minor        bigint                      CHECK (amount_off_minor > 0),
+    currency                varchar(3),
+    min_order_total_minor   bigint                      CHECK (min_order_total_minor >= 0),
+    expires_at              timestamp with time zone,
+    max_uses                integer                     CHECK (max_uses > 0),
+    max_uses_per_user       integer                     CHECK (max_uses_per_user > 0),
+    times_used              integer                     NOT NULL DEFAULT 0,
+    active                  boolean                     NOT NULL DEFAULT true,
+    created_at              timestamp with time zone    NOT NULL,
+    CHECK ((kind = 'percentage') = (percent_off IS NOT NULL)),
+    CHECK ((kind = 'fixed') = (amount_off_minor IS NOT NULL)),
+    CHECK (currency IS NOT NULL OR (amount_off_minor IS NULL AND min_order_total_minor IS NULL)),
+    CHECK (max_uses IS NULL OR times_used <= max_uses)
+);
+
+-- Discount lines of orders. Each row is also one use of its coupon, per user usage is counted from here.
+CREATE TABLE order_discounts
+(
+    discount_id     uuid                        NOT NULL PRIMARY KEY,
+    order_id        uuid                        NOT NULL REFERENCES orders(order_id),
+    coupon_id       uuid                        NOT NULL REFERENCES coupons(coupon_id),
+    user_id         uuid                        NOT NULL REFERENCES users(user_id),
+    code            varchar(64)                 NOT NULL,
+    amount_minor    bigint                      NOT NULL CHECK (amount_minor >= 0),
+    created_at      timestamp with time zone    NOT NULL
+);
+CREATE INDEX order_discounts_order_id_index ON order_discounts (order_id);
+CREATE INDEX order_discounts_coupon_id_user_id_index ON order_discounts (coupon_id, user_id);
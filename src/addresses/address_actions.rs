@@ -0,0 +1,111 @@
+#[path = "./address_models.rs"] pub mod models;
+
+use diesel::prelude::*;
+use models::{Address, AddressDetails};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// Addresses of user, oldest first.
+pub fn find_addresses(uid: Uuid, conn: &PgConnection) -> Result<Vec<AddressDetails>, AppError> {
+    // It is common when using Diesel with Actix web to import schema-related
+    // modules inside a function's scope (rather than the normal module's scope)
+    // to prevent import collisions and namespace pollution.
+    use crate::schema::addresses::dsl::*;
+
+    let list = addresses
+        .filter(user_id.eq(uid))
+        .order((created_at.asc(), address_id.asc()))
+        .load::<Address>(conn)?;
+
+    Ok(list.into_iter().map(AddressDetails::from).collect())
+}
+
+/// Find address of user by address_id. Addresses of other users are not found.
+pub fn find_address_by_id(uid: Uuid, aid: Uuid, conn: &PgConnection) -> Result<AddressDetails, AppError> {
+    use crate::schema::addresses::dsl::*;
+
+    let address = addresses
+        // Only allow to access user’s own address not others
+        .filter(user_id.eq(uid))
+        .filter(address_id.eq(aid))
+        .first::<Address>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Address is not found.".to_owned()))?;
+
+    Ok(address.into())
+}
+
+pub fn insert_new_address(
+    uid: Uuid,
+    new_address: &models::NewAddress,
+    conn: &PgConnection,
+) -> Result<AddressDetails, AppError> {
+    use crate::schema::addresses::dsl::*;
+
+    let now = chrono::offset::Utc::now().naive_utc();
+
+    let address = Address {
+        address_id: Uuid::new_v4(),
+        user_id: uid,
+        label: new_address.label.clone(),
+        full_name: new_address.full_name.clone(),
+        line1: new_address.line1.clone(),
+        line2: new_address.line2.clone(),
+        city: new_address.city.clone(),
+        region: new_address.region.clone(),
+        postal_code: new_address.postal_code.clone(),
+        country: new_address.country.clone(),
+        phone: new_address.phone.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    diesel::insert_into(addresses)
+        .values(&address)
+        .execute(conn)?;
+
+    Ok(address.into())
+}
+
+/// Replace address of user. Orders already placed with it keep address as it was.
+pub fn replace_address(
+    uid: Uuid,
+    aid: Uuid,
+    new_address: &models::NewAddress,
+    conn: &PgConnection,
+) -> Result<AddressDetails, AppError> {
+    use crate::schema::addresses::dsl::*;
+
+    let address = diesel::update(addresses.filter(user_id.eq(uid)).filter(address_id.eq(aid)))
+        .set((
+            label.eq(&new_address.label),
+            full_name.eq(&new_address.full_name),
+            line1.eq(&new_address.line1),
+            line2.eq(&new_address.line2),
+            city.eq(&new_address.city),
+            region.eq(&new_address.region),
+            postal_code.eq(&new_address.postal_code),
+            country.eq(&new_address.country),
+            phone.eq(&new_address.phone),
+            updated_at.eq(chrono::offset::Utc::now().naive_utc()),
+        ))
+        .get_result::<Address>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Address is not found.".to_owned()))?;
+
+    Ok(address.into())
+}
+
+/// Delete address of user. Orders already placed with it keep their copy of it.
+pub fn delete_address(uid: Uuid, aid: Uuid, conn: &PgConnection) -> Result<(), AppError> {
+    use crate::schema::addresses::dsl::*;
+
+    let deleted = diesel::delete(addresses.filter(user_id.eq(uid)).filter(address_id.eq(aid))).execute(conn)?;
+
+    if deleted == 0 {
+        return Err(AppError::NotFound("Address is not found.".to_owned()));
+    }
+
+    Ok(())
+}
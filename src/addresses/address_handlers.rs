@@ -0,0 +1,101 @@
+//! Diesel does not support async operations, i.e. diesel operations are blocking, so we have to run it in separate threads using the web::block
+//! function which offloads blocking code (like Diesel's) in order to not block the server's thread.
+
+use actix_web::http::header;
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::users::auth::AuthenticatedUser;
+
+#[path = "./address_actions.rs"] mod actions;
+
+type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Lists address book of user.
+#[get("/api/v1/users/me/addresses")]
+pub async fn get_addresses(user: AuthenticatedUser, pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let list = web::block(move || actions::find_addresses(user.user_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// Finds address of user by UID.
+#[get("/api/v1/users/me/addresses/{address_id}")]
+pub async fn get_address_by_id(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    address_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    let address_id = address_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let address = web::block(move || actions::find_address_by_id(user.user_id, address_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(address))
+}
+
+/// Adds address to address book of user.
+#[post("/api/v1/users/me/addresses")]
+pub async fn create_address(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    body: web::Json<actions::models::NewAddress>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate_address()?;
+
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let address = web::block(move || actions::insert_new_address(user.user_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Created()
+        .header(header::LOCATION, format!("/api/v1/users/me/addresses/{}", address.address_id))
+        .json(address))
+}
+
+/// Replaces address of user with given one.
+#[put("/api/v1/users/me/addresses/{address_id}")]
+pub async fn replace_address(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    address_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::NewAddress>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate_address()?;
+
+    let conn = pool.get()?;
+
+    let address_id = address_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let address = web::block(move || actions::replace_address(user.user_id, address_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(address))
+}
+
+/// Removes address from address book of user. Responds with 204.
+#[delete("/api/v1/users/me/addresses/{address_id}")]
+pub async fn delete_address(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    address_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    let address_id = address_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    web::block(move || actions::delete_address(user.user_id, address_id, &conn)).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
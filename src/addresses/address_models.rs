@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::schema::addresses;
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "addresses"]
+pub struct Address {
+    pub address_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    // Name given by user, e.g. "Home" or "Work".
+    pub label: Option<String>,
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    // State, province or county.
+    pub region: Option<String>,
+    pub postal_code: String,
+    // ISO 3166-1 alpha-2 code.
+    pub country: String,
+    pub phone: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressDetails {
+    pub address_id: uuid::Uuid,
+    pub label: Option<String>,
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+    pub phone: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl From<Address> for AddressDetails {
+    fn from(address: Address) -> Self {
+        AddressDetails {
+            address_id: address.address_id,
+            label: address.label,
+            full_name: address.full_name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+            phone: address.phone,
+            created_at: address.created_at,
+            updated_at: address.updated_at,
+        }
+    }
+}
+
+/// Address to add to address book, or to replace existing one with. Length limits match varchar columns of
+/// addresses table.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct NewAddress {
+    #[validate(length(min = 1, max = 100, message = "must be between 1 and 100 characters"))]
+    pub label: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub full_name: String,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub line1: String,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub line2: Option<String>,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub city: String,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub region: Option<String>,
+    #[validate(custom = "validate_postal_code_chars")]
+    pub postal_code: String,
+    #[validate(custom = "validate_country")]
+    pub country: String,
+    #[validate(custom = "validate_phone")]
+    pub phone: Option<String>,
+}
+
+impl NewAddress {
+    /// Validate fields, and postal code against format used in its country. Format is only known for some
+    /// countries, others just get the generic check of characters.
+    pub fn validate_address(&self) -> Result<(), ValidationErrors> {
+        let mut errors = match self.validate() {
+            Ok(()) => ValidationErrors::new(),
+            Err(errors) => errors,
+        };
+
+        if !errors.field_errors().contains_key("postal_code") {
+            if let Err(err) = validate_postal_code_format(&self.country, &self.postal_code) {
+                errors.add("postal_code", err);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// ISO 3166-1 alpha-2 codes of countries addresses can be in.
+static COUNTRY_CODES: &str = "\
+    AD AE AF AG AI AL AM AO AQ AR AS AT AU AW AX AZ \
+    BA BB BD BE BF BG BH BI BJ BL BM BN BO BQ BR BS BT BV BW BY BZ \
+    CA CC CD CF CG CH CI CK CL CM CN CO CR CU CV CW CX CY CZ \
+    DE DJ DK DM DO DZ EC EE EG EH ER ES ET FI FJ FK FM FO FR \
+    GA GB GD GE GF GG GH GI GL GM GN GP GQ GR GS GT GU GW GY \
+    HK HM HN HR HT HU ID IE IL IM IN IO IQ IR IS IT JE JM JO JP \
+    KE KG KH KI KM KN KP KR KW KY KZ LA LB LC LI LK LR LS LT LU LV LY \
+    MA MC MD ME MF MG MH MK ML MM MN MO MP MQ MR MS MT MU MV MW MX MY MZ \
+    NA NC NE NF NG NI NL NO NP NR NU NZ OM PA PE PF PG PH PK PL PM PN PR PS PT PW PY \
+    QA RE RO RS RU RW SA SB SC SD SE SG SH SI SJ SK SL SM SN SO SR SS ST SV SX SY SZ \
+    TC TD TF TG TH TJ TK TL TM TN TO TR TT TV TW TZ UA UG UM US UY UZ \
+    VA VC VE VG VI VN VU WF WS YE YT ZA ZM ZW";
+
+/// Postal code formats of some countries. In patterns `9` stands for digit, `A` for letter and anything else for
+/// itself.
+static POSTAL_CODE_FORMATS: &[(&str, &[&str])] = &[
+    ("AU", &["9999"]),
+    ("CA", &["A9A 9A9"]),
+    ("DE", &["99999"]),
+    ("ES", &["99999"]),
+    ("FR", &["99999"]),
+    ("GB", &["A9 9AA", "A99 9AA", "A9A 9AA", "AA9 9AA", "AA99 9AA", "AA9A 9AA"]),
+    ("IN", &["999999"]),
+    ("IT", &["99999"]),
+    ("JP", &["999-9999"]),
+    ("NL", &["9999 AA"]),
+    ("US", &["99999", "99999-9999"]),
+];
+
+fn validate_country(country: &str) -> Result<(), ValidationError> {
+    if country.len() == 2 && COUNTRY_CODES.split(' ').any(|code| code == country) {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("country");
+        err.message = Some("must be ISO 3166-1 alpha-2 country code in upper case, e.g. 'US'".into());
+        Err(err)
+    }
+}
+
+fn validate_postal_code_chars(postal_code: &str) -> Result<(), ValidationError> {
+    let valid_chars = postal_code.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ' || c == '-');
+    if (2..=20).contains(&postal_code.len()) && valid_chars {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("postal_code");
+        err.message = Some("must be 2 to 20 letters, digits, spaces or '-'".into());
+        Err(err)
+    }
+}
+
+fn validate_postal_code_format(country: &str, postal_code: &str) -> Result<(), ValidationError> {
+    let formats = match POSTAL_CODE_FORMATS.iter().find(|(code, _)| *code == country) {
+        Some((_, formats)) => formats,
+        None => return Ok(()),
+    };
+
+    let matches = |format: &str| {
+        format.len() == postal_code.len()
+            && format.chars().zip(postal_code.chars()).all(|(f, c)| match f {
+                '9' => c.is_ascii_digit(),
+                'A' => c.is_ascii_uppercase(),
+                _ => f == c,
+            })
+    };
+
+    if formats.iter().any(|format| matches(format)) {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("postal_code");
+        let expected = formats.join(" or ");
+        err.message = Some(format!("is not valid postal code for {}, expected format: {}", country, expected).into());
+        Err(err)
+    }
+}
+
+fn validate_phone(phone: &str) -> Result<(), ValidationError> {
+    let valid_chars = phone.chars().all(|c| c.is_ascii_digit() || " +-()".contains(c));
+    if (1..=32).contains(&phone.len()) && valid_chars {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("phone");
+        err.message = Some("must be 1 to 32 digits, spaces, '+', '-', '(' or ')'".into());
+        Err(err)
+    }
+}
@@ -0,0 +1,189 @@
+#[path = "./cart_models.rs"] pub mod models;
+#[path = "../orders/order_actions.rs"] pub mod order_actions;
+
+use diesel::prelude::*;
+use models::{CartDetails, CartItem, CartItemDetails};
+use uuid::Uuid;
+
+use crate::errors::{AppError, FieldError};
+use crate::money::{Currency, Money};
+
+/// Cart of user with current price of each product. Items are in order they were added, which is also order of
+/// items of order made by checkout.
+pub fn find_cart(uid: Uuid, conn: &PgConnection) -> Result<CartDetails, AppError> {
+    use crate::schema::cart_items::dsl as ci;
+    use crate::schema::products::dsl as p;
+
+    let rows = ci::cart_items
+        .inner_join(p::products)
+        .filter(ci::user_id.eq(uid))
+        .order((ci::added_at.asc(), ci::product_id.asc()))
+        .select((ci::product_id, ci::qty, p::sku, p::name, p::price_minor, p::currency, p::active, p::stock_available))
+        .load::<(Uuid, i32, String, String, i64, Currency, bool, i32)>(conn)?;
+
+    let items = rows
+        .into_iter()
+        .map(|(pid, qty, sku, name, price_minor, currency, active, stock_available)| {
+            let unit_price = Money::new(price_minor, currency);
+            Ok(CartItemDetails {
+                product_id: pid,
+                sku,
+                name,
+                qty,
+                unit_price,
+                line_total: unit_price.checked_mul(qty).ok_or_else(cart_too_large)?,
+                available: active && stock_available >= qty,
+            })
+        })
+        .collect::<Result<Vec<CartItemDetails>, AppError>>()?;
+
+    let total = match items.first() {
+        Some(first) if items.iter().all(|item| item.line_total.currency == first.line_total.currency) => {
+            let sum = items.iter().try_fold(0i64, |sum, item| sum.checked_add(item.line_total.amount_minor));
+            Some(Money::new(sum.ok_or_else(cart_too_large)?, first.line_total.currency))
+        }
+        _ => None,
+    };
+
+    Ok(CartDetails { items, total })
+}
+
+/// Put product in cart of user, or increase its quantity when it is already there.
+pub fn add_cart_item(uid: Uuid, new_item: &models::NewCartItem, conn: &PgConnection) -> Result<CartDetails, AppError> {
+    use crate::schema::cart_items::dsl::*;
+
+    conn.transaction::<_, AppError, _>(|| {
+        let available = {
+            use crate::schema::products::dsl as p;
+
+            p::products
+                .select(p::product_id)
+                .filter(p::product_id.eq(new_item.product_id))
+                .filter(p::active.eq(true))
+                .first::<Uuid>(conn)
+                .optional()?
+                .is_some()
+        };
+        if !available {
+            return Err(AppError::Validation(vec![FieldError::new("product_id", "is not an available product")]));
+        }
+
+        let in_cart: Vec<(Uuid, i32)> = cart_items.select((product_id, qty)).filter(user_id.eq(uid)).load(conn)?;
+        let qty_in_cart = in_cart.iter().find(|(pid, _)| *pid == new_item.product_id).map(|(_, item_qty)| *item_qty);
+        if qty_in_cart.is_none() && in_cart.len() >= order_actions::MAX_ITEMS_PER_ORDER {
+            return Err(AppError::Validation(vec![FieldError::new(
+                "product_id",
+                &format!("can not be added, cart already has {} products", order_actions::MAX_ITEMS_PER_ORDER),
+            )]));
+        }
+
+        if qty_in_cart.unwrap_or(0) + new_item.qty > order_actions::MAX_ITEM_QTY {
+            return Err(AppError::Validation(vec![FieldError::new(
+                "qty",
+                &format!("can not be added, cart can hold at most {} of product", order_actions::MAX_ITEM_QTY),
+            )]));
+        }
+
+        let now = chrono::offset::Utc::now().naive_utc();
+
+        let item = CartItem {
+            user_id: uid,
+            product_id: new_item.product_id,
+            qty: new_item.qty,
+            added_at: now,
+            updated_at: now,
+        };
+
+        diesel::insert_into(cart_items)
+            .values(&item)
+            .on_conflict((user_id, product_id))
+            .do_update()
+            .set((qty.eq(qty + new_item.qty), updated_at.eq(now)))
+            .execute(conn)?;
+
+        find_cart(uid, conn)
+    })
+}
+
+/// Set quantity of product which is in cart.
+pub fn update_cart_item(
+    uid: Uuid,
+    pid: Uuid,
+    update: &models::CartItemUpdate,
+    conn: &PgConnection,
+) -> Result<CartDetails, AppError> {
+    use crate::schema::cart_items::dsl::*;
+
+    conn.transaction::<_, AppError, _>(|| {
+        let updated = diesel::update(cart_items.filter(user_id.eq(uid)).filter(product_id.eq(pid)))
+            .set((qty.eq(update.qty), updated_at.eq(chrono::offset::Utc::now().naive_utc())))
+            .execute(conn)?;
+
+        if updated == 0 {
+            return Err(AppError::NotFound("Product is not in cart.".to_owned()));
+        }
+
+        find_cart(uid, conn)
+    })
+}
+
+/// Take product out of cart.
+pub fn remove_cart_item(uid: Uuid, pid: Uuid, conn: &PgConnection) -> Result<CartDetails, AppError> {
+    use crate::schema::cart_items::dsl::*;
+
+    let deleted = diesel::delete(cart_items.filter(user_id.eq(uid)).filter(product_id.eq(pid))).execute(conn)?;
+
+    if deleted == 0 {
+        return Err(AppError::NotFound("Product is not in cart.".to_owned()));
+    }
+
+    find_cart(uid, conn)
+}
+
+fn cart_too_large() -> AppError {
+    AppError::Validation(vec![FieldError::new("items", "total amount of cart is too large")])
+}
+
+/// Turn cart of user into order and empty the cart, in single transaction. Order is priced, checked and stock is
+/// taken exactly like for order created directly, so failure of any of it leaves cart as it was.
+pub fn checkout(
+    uid: Uuid,
+    oid: Uuid,
+    checkout: &models::Checkout,
+    conn: &PgConnection,
+) -> Result<order_actions::models::OrderDetails, AppError> {
+    use crate::schema::cart_items::dsl::*;
+
+    conn.transaction::<_, AppError, _>(|| {
+        // Lock cart rows so that concurrent checkout waits and then finds the cart empty.
+        let items = cart_items
+            .filter(user_id.eq(uid))
+            .order((added_at.asc(), product_id.asc()))
+            .for_update()
+            .load::<CartItem>(conn)?;
+
+        if items.is_empty() {
+            return Err(AppError::Conflict("Cart is empty.".to_owned()));
+        }
+
+        let new_order = order_actions::models::NewOrder {
+            note: checkout.note.clone(),
+            items: items
+                .iter()
+                .map(|item| order_actions::models::NewOrderItem {
+                    product_id: item.product_id,
+                    qty: item.qty,
+                })
+                .collect(),
+            shipping_address_id: checkout.shipping_address_id,
+            billing_address_id: checkout.billing_address_id,
+            coupon_code: checkout.coupon_code.clone(),
+        };
+
+        let order = order_actions::create_order_with_items(oid, uid, &new_order, conn)?;
+
+        diesel::delete(cart_items.filter(user_id.eq(uid))).execute(conn)?;
+
+        Ok(order)
+    })
+}
@@ -0,0 +1,105 @@
+//! Diesel does not support async operations, i.e. diesel operations are blocking, so we have to run it in separate threads using the web::block
+//! function which offloads blocking code (like Diesel's) in order to not block the server's thread.
+
+use actix_web::http::header;
+use actix_web::{delete, get, patch, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::errors::AppError;
+use crate::users::auth::AuthenticatedUser;
+
+#[path = "./cart_actions.rs"] mod actions;
+
+type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Shows cart of user with current prices.
+#[get("/api/v1/cart")]
+pub async fn get_cart(user: AuthenticatedUser, pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let cart = web::block(move || actions::find_cart(user.user_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+/// Puts product in cart. Responds with whole cart.
+#[post("/api/v1/cart/items")]
+pub async fn add_cart_item(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    body: web::Json<actions::models::NewCartItem>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let cart = web::block(move || actions::add_cart_item(user.user_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+/// Changes quantity of product in cart. Responds with whole cart.
+#[patch("/api/v1/cart/items/{product_id}")]
+pub async fn update_cart_item(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    product_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::CartItemUpdate>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    let product_id = product_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let cart = web::block(move || actions::update_cart_item(user.user_id, product_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+/// Takes product out of cart. Responds with whole cart.
+#[delete("/api/v1/cart/items/{product_id}")]
+pub async fn remove_cart_item(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    product_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    let product_id = product_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let cart = web::block(move || actions::remove_cart_item(user.user_id, product_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+/// Places order with everything in cart and empties the cart. Responds with 201 and details of created order.
+#[post("/api/v1/cart/checkout")]
+pub async fn checkout(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    body: web::Json<actions::models::Checkout>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    let order_id = Uuid::new_v4();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let order = web::block(move || actions::checkout(user.user_id, order_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Created()
+        .header(header::LOCATION, format!("/api/v1/orders/{}", order_id))
+        .json(order))
+}
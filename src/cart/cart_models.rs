@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::money::Money;
+use crate::schema::cart_items;
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct CartItem {
+    pub user_id: uuid::Uuid,
+    pub product_id: uuid::Uuid,
+    pub qty: i32,
+    pub added_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime
+}
+
+/// Product to put in cart. Adding product which is already in cart increases its quantity.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct NewCartItem {
+    pub product_id: uuid::Uuid,
+    #[validate(range(min = 1, max = 10000, message = "must be between 1 and 10000"))]
+    pub qty: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CartItemUpdate {
+    #[validate(range(min = 1, max = 10000, message = "must be between 1 and 10000"))]
+    pub qty: i32,
+}
+
+// Length limits match varchar columns of orders table.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct Checkout {
+    #[validate(length(max = 500, message = "must be at most 500 characters"))]
+    pub note: Option<String>,
+    // Same as in new order, billing address defaults to shipping address.
+    pub shipping_address_id: Option<uuid::Uuid>,
+    pub billing_address_id: Option<uuid::Uuid>,
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    pub coupon_code: Option<String>,
+}
+
+/// Item of cart with current name and price of its product.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CartItemDetails {
+    pub product_id: uuid::Uuid,
+    pub sku: String,
+    pub name: String,
+    pub qty: i32,
+    pub unit_price: Money,
+    pub line_total: Money,
+    // Whether product can be ordered in this quantity right now.
+    pub available: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CartDetails {
+    pub items: Vec<CartItemDetails>,
+    // Absent when cart is empty or has products priced in different currencies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<Money>,
+}
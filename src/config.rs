@@ -0,0 +1,160 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use std::collections::HashMap;
+
+// Shorter secrets can be brute forced from any token signed with them.
+static MIN_JWT_SECRET_LEN: usize = 32;
+
+/// Keys used to sign and verify jwt tokens. Each key is identified by `kid` which is put in token header.
+///
+/// Keys are loaded from `JWT_KEYS` environment variable in format `kid1:secret1,kid2:secret2`.
+/// First key is the active one used for signing, rest are only accepted for verification so that
+/// tokens signed with previous key stay valid while secret is being rotated.
+/// For single key setups `JWT_SECRET` can be used instead, in which case kid is "default".
+/// Every secret must be at least `MIN_JWT_SECRET_LEN` bytes long, otherwise startup fails.
+#[derive(Clone)]
+pub struct JwtKeys {
+    active_kid: String,
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl JwtKeys {
+    pub fn from_env() -> Result<JwtKeys, String> {
+        if let Ok(spec) = std::env::var("JWT_KEYS") {
+            return JwtKeys::parse(&spec);
+        }
+
+        let secret = std::env::var("JWT_SECRET")
+            .map_err(|_| "JWT_KEYS or JWT_SECRET must be set in environment variable".to_owned())?;
+
+        JwtKeys::parse(&format!("default:{}", secret))
+    }
+
+    fn parse(spec: &str) -> Result<JwtKeys, String> {
+        let mut active_kid: Option<String> = None;
+        let mut keys: HashMap<String, Vec<u8>> = HashMap::new();
+
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kid, secret) = match entry.find(':') {
+                Some(idx) => (&entry[..idx], &entry[idx + 1..]),
+                None => return Err(format!("jwt key entry '{}' should be in kid:secret format", entry)),
+            };
+
+            if kid.is_empty() || secret.is_empty() {
+                return Err(format!("jwt key entry '{}' has empty kid or secret", entry));
+            }
+
+            if secret.len() < MIN_JWT_SECRET_LEN {
+                return Err(format!("jwt key '{}' should be at least {} bytes long", kid, MIN_JWT_SECRET_LEN));
+            }
+
+            if keys.insert(kid.to_owned(), secret.as_bytes().to_vec()).is_some() {
+                return Err(format!("jwt key id '{}' is present more than once", kid));
+            }
+
+            active_kid.get_or_insert_with(|| kid.to_owned());
+        }
+
+        let active_kid = active_kid.ok_or_else(|| "at least one jwt key must be configured".to_owned())?;
+
+        Ok(JwtKeys { active_kid, keys })
+    }
+
+    /// Key id and secret to sign new tokens with.
+    pub fn signing_key(&self) -> (&str, &[u8]) {
+        (&self.active_kid, &self.keys[&self.active_kid])
+    }
+
+    /// Secret for given key id if it is in keyring.
+    pub fn verification_key(&self, kid: &str) -> Option<&[u8]> {
+        self.keys.get(kid).map(|k| k.as_slice())
+    }
+}
+
+/// Header from which access token is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenHeader {
+    /// Standard `Authorization: Bearer <jwt>` header.
+    Authorization,
+    /// Custom `access_token: <jwt>` header.
+    AccessToken,
+}
+
+/// Order in which headers are looked up for access token. Both headers are accepted, this only decides which one
+/// wins when request carries both.
+///
+/// Loaded from `AUTH_TOKEN_PRECEDENCE` environment variable which can be `authorization` (default) or `access_token`.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenHeaderPrecedence(pub [TokenHeader; 2]);
+
+impl TokenHeaderPrecedence {
+    pub fn from_env() -> Result<TokenHeaderPrecedence, String> {
+        match std::env::var("AUTH_TOKEN_PRECEDENCE") {
+            Err(_) => Ok(TokenHeaderPrecedence::default()),
+            Ok(v) => match v.trim().to_lowercase().as_str() {
+                "authorization" => Ok(TokenHeaderPrecedence([TokenHeader::Authorization, TokenHeader::AccessToken])),
+                "access_token" => Ok(TokenHeaderPrecedence([TokenHeader::AccessToken, TokenHeader::Authorization])),
+                other => Err(format!("AUTH_TOKEN_PRECEDENCE should be 'authorization' or 'access_token', got '{}'", other)),
+            },
+        }
+    }
+}
+
+impl Default for TokenHeaderPrecedence {
+    fn default() -> Self {
+        TokenHeaderPrecedence([TokenHeader::Authorization, TokenHeader::AccessToken])
+    }
+}
+
+// Defaults follow the OWASP recommendation for Argon2id (19 MiB, 2 iterations, 1 lane).
+static DEFAULT_ARGON2_MEMORY_COST_KIB: u32 = 19 * 1024;
+static DEFAULT_ARGON2_TIME_COST: u32 = 2;
+static DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+
+/// Argon2id costs used to hash new passwords.
+///
+/// Loaded from `ARGON2_MEMORY_COST_KIB`, `ARGON2_TIME_COST` and `ARGON2_PARALLELISM` environment variables, each
+/// falling back to its default when absent. Values which are not numbers or which Argon2 rejects fail startup.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    // Hash of random password, verified against when login email is unknown so that it takes as long as wrong
+    // password and does not tell which emails are registered.
+    dummy_hash: String,
+}
+
+impl PasswordHashing {
+    pub fn from_env() -> Result<PasswordHashing, String> {
+        let params = Params::new(
+            env_u32("ARGON2_MEMORY_COST_KIB", DEFAULT_ARGON2_MEMORY_COST_KIB)?,
+            env_u32("ARGON2_TIME_COST", DEFAULT_ARGON2_TIME_COST)?,
+            env_u32("ARGON2_PARALLELISM", DEFAULT_ARGON2_PARALLELISM)?,
+            None,
+        )
+        .map_err(|e| format!("invalid ARGON2_* settings: {}", e))?;
+
+        let salt = SaltString::generate(&mut OsRng);
+        let dummy_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+            .hash_password(salt.as_str().as_bytes(), &salt)
+            .map_err(|e| format!("couldn't hash dummy password: {}", e))?
+            .to_string();
+
+        Ok(PasswordHashing { params, dummy_hash })
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn dummy_hash(&self) -> &str {
+        &self.dummy_hash
+    }
+}
+
+/// Read u32 from environment variable, `default` when it is absent.
+fn env_u32(name: &str, default: u32) -> Result<u32, String> {
+    match std::env::var(name) {
+        Err(_) => Ok(default),
+        Ok(v) => v.trim().parse::<u32>().map_err(|_| format!("{} should be positive integer, got '{}'", name, v)),
+    }
+}
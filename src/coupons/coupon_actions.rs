@@ -0,0 +1,107 @@
+#[path = "./coupon_models.rs"] pub mod models;
+
+use diesel::prelude::*;
+use models::CouponDetails;
+use uuid::Uuid;
+
+use crate::discounts::{self, Coupon};
+use crate::errors::{AppError, FieldError};
+
+/// List all coupons, newest first.
+pub fn find_all_coupons(conn: &PgConnection) -> Result<Vec<CouponDetails>, AppError> {
+    // It is common when using Diesel with Actix web to import schema-related
+    // modules inside a function's scope (rather than the normal module's scope)
+    // to prevent import collisions and namespace pollution.
+    use crate::schema::coupons::dsl::*;
+
+    let list = coupons
+        .order((created_at.desc(), code.asc()))
+        .load::<Coupon>(conn)?;
+
+    Ok(list.into_iter().map(CouponDetails::from).collect())
+}
+
+pub fn find_coupon_by_id(cid: Uuid, conn: &PgConnection) -> Result<CouponDetails, AppError> {
+    use crate::schema::coupons::dsl::*;
+
+    let coupon = coupons
+        .filter(coupon_id.eq(cid))
+        .first::<Coupon>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Coupon is not found.".to_owned()))?;
+
+    Ok(coupon.into())
+}
+
+/// Insert new coupon. Code is stored in upper case, code which is already taken is rejected with 409.
+pub fn insert_new_coupon(new_coupon: &models::NewCoupon, conn: &PgConnection) -> Result<CouponDetails, AppError> {
+    use crate::schema::coupons::dsl::*;
+
+    let coupon = Coupon {
+        coupon_id: Uuid::new_v4(),
+        code: discounts::normalize_code(&new_coupon.code),
+        kind: new_coupon.kind,
+        percent_off: new_coupon.percent_off,
+        amount_off_minor: new_coupon.amount_off.map(|m| m.amount_minor),
+        currency: new_coupon.amount_off.or(new_coupon.min_order_total).map(|m| m.currency),
+        min_order_total_minor: new_coupon.min_order_total.map(|m| m.amount_minor),
+        expires_at: new_coupon.expires_at.map(|at| at.naive_utc()),
+        max_uses: new_coupon.max_uses,
+        max_uses_per_user: new_coupon.max_uses_per_user,
+        times_used: 0,
+        active: new_coupon.active,
+        created_at: chrono::offset::Utc::now().naive_utc(),
+    };
+
+    diesel::insert_into(coupons)
+        .values(&coupon)
+        .execute(conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => {
+                AppError::Conflict(format!("Coupon with code '{}' already exists.", coupon.code))
+            }
+            e => AppError::Db(e),
+        })?;
+
+    Ok(coupon.into())
+}
+
+/// Change active flag, expiry or usage limits of coupon. Orders which already used it are not affected.
+pub fn update_coupon(cid: Uuid, update: &models::CouponUpdate, conn: &PgConnection) -> Result<CouponDetails, AppError> {
+    use crate::schema::coupons::dsl::*;
+
+    conn.transaction::<_, AppError, _>(|| {
+        let coupon = coupons
+            .filter(coupon_id.eq(cid))
+            .for_update()
+            .first::<Coupon>(conn)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound("Coupon is not found.".to_owned()))?;
+
+        if update.max_uses.is_some_and(|max| max < coupon.times_used) {
+            return Err(AppError::Validation(vec![FieldError::new(
+                "max_uses",
+                &format!("must not be less than number of times coupon was already used ({})", coupon.times_used),
+            )]));
+        }
+
+        let updated = Coupon {
+            active: update.active.unwrap_or(coupon.active),
+            expires_at: update.expires_at.map(|at| at.naive_utc()).or(coupon.expires_at),
+            max_uses: update.max_uses.or(coupon.max_uses),
+            max_uses_per_user: update.max_uses_per_user.or(coupon.max_uses_per_user),
+            ..coupon
+        };
+
+        diesel::update(coupons.filter(coupon_id.eq(cid)))
+            .set((
+                active.eq(updated.active),
+                expires_at.eq(updated.expires_at),
+                max_uses.eq(updated.max_uses),
+                max_uses_per_user.eq(updated.max_uses_per_user),
+            ))
+            .execute(conn)?;
+
+        Ok(updated.into())
+    })
+}
@@ -0,0 +1,93 @@
+//! Diesel does not support async operations, i.e. diesel operations are blocking, so we have to run it in separate threads using the web::block
+//! function which offloads blocking code (like Diesel's) in order to not block the server's thread.
+
+use actix_web::http::header;
+use actix_web::{get, patch, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::errors::AppError;
+use crate::users::auth::AuthenticatedUser;
+
+#[path = "./coupon_actions.rs"] mod actions;
+
+type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Lists all coupons with their usage. Admin only.
+#[get("/api/v1/coupons")]
+pub async fn get_coupons(user: AuthenticatedUser, pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let list = web::block(move || actions::find_all_coupons(&conn)).await?;
+
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// Finds coupon by UID. Admin only.
+#[get("/api/v1/coupons/{coupon_id}")]
+pub async fn get_coupon_by_id(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    coupon_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    let conn = pool.get()?;
+
+    let coupon_id = coupon_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let coupon = web::block(move || actions::find_coupon_by_id(coupon_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(coupon))
+}
+
+/// Adds coupon. Admin only.
+#[post("/api/v1/coupons")]
+pub async fn create_coupon(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    body: web::Json<actions::models::NewCoupon>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    // Reject invalid payload before any db work.
+    body.validate_coupon()?;
+
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let coupon = web::block(move || actions::insert_new_coupon(&body, &conn)).await?;
+
+    Ok(HttpResponse::Created()
+        .header(header::LOCATION, format!("/api/v1/coupons/{}", coupon.coupon_id))
+        .json(coupon))
+}
+
+/// Changes active flag, expiry or usage limits of coupon. Admin only.
+#[patch("/api/v1/coupons/{coupon_id}")]
+pub async fn update_coupon(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    coupon_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::CouponUpdate>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    let coupon_id = coupon_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let coupon = web::block(move || actions::update_coupon(coupon_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(coupon))
+}
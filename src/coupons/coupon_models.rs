@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::discounts::{Coupon, DiscountKind};
+use crate::money::Money;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CouponDetails {
+    pub coupon_id: uuid::Uuid,
+    pub code: String,
+    pub kind: DiscountKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percent_off: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_off: Option<Money>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_order_total: Option<Money>,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub times_used: i32,
+    pub active: bool,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl From<Coupon> for CouponDetails {
+    fn from(coupon: Coupon) -> Self {
+        CouponDetails {
+            amount_off: coupon.amount_off(),
+            min_order_total: coupon.min_order_total(),
+            coupon_id: coupon.coupon_id,
+            code: coupon.code,
+            kind: coupon.kind,
+            percent_off: coupon.percent_off,
+            expires_at: coupon.expires_at,
+            max_uses: coupon.max_uses,
+            max_uses_per_user: coupon.max_uses_per_user,
+            times_used: coupon.times_used,
+            active: coupon.active,
+            created_at: coupon.created_at,
+        }
+    }
+}
+
+/// New coupon. Percentage coupons need `percent_off` and fixed ones `amount_off`. `amount_off` and
+/// `min_order_total` must be in same currency, and coupon then only applies to orders in it.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct NewCoupon {
+    #[validate(custom = "validate_code")]
+    pub code: String,
+    pub kind: DiscountKind,
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    pub percent_off: Option<i32>,
+    #[validate(custom = "validate_positive")]
+    pub amount_off: Option<Money>,
+    #[validate(custom = "crate::payload::validate_non_negative")]
+    pub min_order_total: Option<Money>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[validate(range(min = 1, message = "must be greater than 0"))]
+    pub max_uses: Option<i32>,
+    #[validate(range(min = 1, message = "must be greater than 0"))]
+    pub max_uses_per_user: Option<i32>,
+    #[serde(default = "crate::payload::default_active")]
+    pub active: bool,
+}
+
+impl NewCoupon {
+    /// Validate fields, and that they fit together: amount fields match `kind` and share currency.
+    pub fn validate_coupon(&self) -> Result<(), ValidationErrors> {
+        let mut errors = match self.validate() {
+            Ok(()) => ValidationErrors::new(),
+            Err(errors) => errors,
+        };
+
+        match self.kind {
+            DiscountKind::Percentage => {
+                if self.percent_off.is_none() {
+                    errors.add("percent_off", field_error("required", "is required for percentage coupon"));
+                }
+                if self.amount_off.is_some() {
+                    errors.add("amount_off", field_error("kind", "must not be given for percentage coupon"));
+                }
+            }
+            DiscountKind::Fixed => {
+                if self.amount_off.is_none() {
+                    errors.add("amount_off", field_error("required", "is required for fixed coupon"));
+                }
+                if self.percent_off.is_some() {
+                    errors.add("percent_off", field_error("kind", "must not be given for fixed coupon"));
+                }
+            }
+        }
+
+        if let (Some(amount_off), Some(min_order_total)) = (self.amount_off, self.min_order_total) {
+            if amount_off.currency != min_order_total.currency {
+                errors.add("min_order_total", field_error("currency", "must be in same currency as amount_off"));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Partial update of coupon. Fields which are absent are left unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CouponUpdate {
+    pub active: Option<bool>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    #[validate(range(min = 1, message = "must be greater than 0"))]
+    pub max_uses: Option<i32>,
+    #[validate(range(min = 1, message = "must be greater than 0"))]
+    pub max_uses_per_user: Option<i32>,
+}
+
+fn field_error(code: &'static str, message: &'static str) -> ValidationError {
+    let mut err = ValidationError::new(code);
+    err.message = Some(message.into());
+    err
+}
+
+fn validate_code(code: &str) -> Result<(), ValidationError> {
+    let valid_chars = code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !code.is_empty() && code.len() <= 64 && valid_chars {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("code");
+        err.message = Some("must be 1 to 64 letters, digits, '-' or '_'".into());
+        Err(err)
+    }
+}
+
+fn validate_positive(amount: &Money) -> Result<(), ValidationError> {
+    if amount.amount_minor > 0 {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("range");
+        err.message = Some("must be greater than 0".into());
+        Err(err)
+    }
+}
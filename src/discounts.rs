@@ -0,0 +1,307 @@
+//! Coupons and discounts of orders.
+//!
+//! Coupon gives either percentage or fixed amount off the subtotal of order. Every use of coupon is a row in
+//! `order_discounts`, which is also discount line of the order. Coupon row is locked while it is being redeemed, so
+//! that concurrent orders are checked against usage limits one after other.
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::Write;
+use uuid::Uuid;
+
+use crate::errors::{AppError, FieldError};
+use crate::money::{Currency, Money};
+use crate::schema::coupons;
+use crate::schema::order_discounts;
+
+/// How coupon discount is computed. Stored as lowercase string in `coupons.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Varchar"]
+pub enum DiscountKind {
+    /// `percent_off` of subtotal, rounded down to minor unit.
+    Percentage,
+    /// `amount_off`, but never more than subtotal.
+    Fixed,
+}
+
+impl DiscountKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscountKind::Percentage => "percentage",
+            DiscountKind::Fixed => "fixed",
+        }
+    }
+}
+
+impl ToSql<Varchar, Pg> for DiscountKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Varchar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Pg> for DiscountKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value: String = FromSql::<Varchar, Pg>::from_sql(bytes)?;
+        match value.as_str() {
+            "percentage" => Ok(DiscountKind::Percentage),
+            "fixed" => Ok(DiscountKind::Fixed),
+            other => Err(format!("unknown discount kind '{}'", other).into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct Coupon {
+    pub coupon_id: Uuid,
+    // Upper case, codes are matched case insensitively.
+    pub code: String,
+    pub kind: DiscountKind,
+    pub percent_off: Option<i32>,
+    pub amount_off_minor: Option<i64>,
+    // Currency of fixed amount and minimum order total. Coupon with currency only applies to orders in it.
+    pub currency: Option<Currency>,
+    pub min_order_total_minor: Option<i64>,
+    pub expires_at: Option<chrono::NaiveDateTime>,
+    // Limit of uses by all users together and by single user, unlimited when absent.
+    pub max_uses: Option<i32>,
+    pub max_uses_per_user: Option<i32>,
+    pub times_used: i32,
+    pub active: bool,
+    pub created_at: chrono::NaiveDateTime
+}
+
+impl Coupon {
+    pub fn amount_off(&self) -> Option<Money> {
+        self.amount_off_minor.zip(self.currency).map(|(amount, currency)| Money::new(amount, currency))
+    }
+
+    pub fn min_order_total(&self) -> Option<Money> {
+        self.min_order_total_minor.zip(self.currency).map(|(amount, currency)| Money::new(amount, currency))
+    }
+
+    /// Discount this coupon gives on given subtotal, in minor units of its currency. Never more than subtotal.
+    pub fn discount_on(&self, subtotal: Money) -> i64 {
+        let amount = match self.kind {
+            // Product is taken in i128, as subtotal times percent may not fit i64 even though the result does.
+            DiscountKind::Percentage => {
+                i128::from(subtotal.amount_minor) * i128::from(self.percent_off.unwrap_or(0)) / 100
+            }
+            DiscountKind::Fixed => i128::from(self.amount_off_minor.unwrap_or(0)),
+        };
+        i64::try_from(amount.min(i128::from(subtotal.amount_minor))).unwrap_or(subtotal.amount_minor)
+    }
+
+    /// Why coupon can not be applied to order with given subtotal, if it can not, ignoring usage limits.
+    fn rejection_for(&self, subtotal: Money, now: chrono::NaiveDateTime) -> Option<String> {
+        if !self.active {
+            return Some("is not a valid coupon code".to_owned());
+        }
+        if self.expires_at.is_some_and(|at| at <= now) {
+            return Some("has expired".to_owned());
+        }
+        if let Some(currency) = self.currency {
+            if currency != subtotal.currency {
+                return Some(format!("is only valid for orders in {}", currency.as_str()));
+            }
+        }
+        match self.min_order_total() {
+            Some(min) if subtotal.amount_minor < min.amount_minor => {
+                Some(format!("requires order total of at least {}", min))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Discount line of order, which is also one use of its coupon.
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct OrderDiscount {
+    pub discount_id: Uuid,
+    pub order_id: Uuid,
+    pub coupon_id: Uuid,
+    pub user_id: Uuid,
+    // Code of coupon at the time of use.
+    pub code: String,
+    // In currency of order.
+    pub amount_minor: i64,
+    pub created_at: chrono::NaiveDateTime
+}
+
+/// Apply coupon with given code to order `order_id_arg` placed by `user_id_arg`, whose items add up to `subtotal`.
+/// Coupon which does not exist, is not active, has expired, does not fit the order or has reached any of its usage
+/// limits is rejected with 422 on `coupon_code`. Must be called in transaction, after order is inserted.
+pub fn redeem_coupon(
+    code_arg: &str,
+    order_id_arg: Uuid,
+    user_id_arg: Uuid,
+    subtotal: Money,
+    conn: &PgConnection,
+) -> Result<OrderDiscount, AppError> {
+    let now = chrono::offset::Utc::now().naive_utc();
+
+    let coupon = {
+        use crate::schema::coupons::dsl::*;
+
+        coupons
+            .filter(code.eq(normalize_code(code_arg)))
+            .for_update()
+            .first::<Coupon>(conn)
+            .optional()?
+            .ok_or_else(|| coupon_error("is not a valid coupon code"))?
+    };
+
+    if let Some(reason) = coupon.rejection_for(subtotal, now) {
+        return Err(coupon_error(&reason));
+    }
+
+    if coupon.max_uses.is_some_and(|max| coupon.times_used >= max) {
+        return Err(coupon_error("has been used up"));
+    }
+
+    if let Some(max) = coupon.max_uses_per_user {
+        use crate::schema::order_discounts::dsl::*;
+
+        let used_by_user: i64 = order_discounts
+            .filter(coupon_id.eq(coupon.coupon_id))
+            .filter(user_id.eq(user_id_arg))
+            .count()
+            .get_result(conn)?;
+        if used_by_user >= i64::from(max) {
+            return Err(coupon_error("has already been used the maximum number of times"));
+        }
+    }
+
+    let discount = OrderDiscount {
+        discount_id: Uuid::new_v4(),
+        order_id: order_id_arg,
+        coupon_id: coupon.coupon_id,
+        user_id: user_id_arg,
+        code: coupon.code.clone(),
+        amount_minor: coupon.discount_on(subtotal),
+        created_at: now,
+    };
+
+    diesel::insert_into(order_discounts::table)
+        .values(&discount)
+        .execute(conn)?;
+
+    {
+        use crate::schema::coupons::dsl::*;
+
+        diesel::update(coupons.filter(coupon_id.eq(coupon.coupon_id)))
+            .set(times_used.eq(times_used + 1))
+            .execute(conn)?;
+    }
+
+    Ok(discount)
+}
+
+/// Recompute discounts of order after its items changed so that its subtotal is now `subtotal`. Percentage discounts
+/// follow the subtotal and fixed ones are capped by it. When subtotal falls below minimum order total of coupon used,
+/// change is rejected with 422 on `items`. Expiry and usage limits are not checked again, coupon was valid when it
+/// was used.
+pub fn reprice_order_discounts(oid: Uuid, subtotal: Money, conn: &PgConnection) -> Result<(), AppError> {
+    use crate::schema::order_discounts::dsl::*;
+
+    let discounts: Vec<(OrderDiscount, Coupon)> = order_discounts
+        .inner_join(coupons::table)
+        .filter(order_id.eq(oid))
+        .load(conn)?;
+
+    let mut errors: Vec<FieldError> = vec![];
+
+    for (discount, coupon) in discounts {
+        if let Some(min) = coupon.min_order_total() {
+            if subtotal.amount_minor < min.amount_minor {
+                errors.push(FieldError::new(
+                    "items",
+                    &format!("order total must stay at least {} for coupon '{}'", min, discount.code),
+                ));
+                continue;
+            }
+        }
+
+        diesel::update(order_discounts.filter(discount_id.eq(discount.discount_id)))
+            .set(amount_minor.eq(coupon.discount_on(subtotal)))
+            .execute(conn)?;
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::Validation(errors));
+    }
+
+    Ok(())
+}
+
+/// Discount lines of order, in order they were applied.
+pub fn find_order_discounts(oid: Uuid, conn: &PgConnection) -> Result<Vec<OrderDiscount>, AppError> {
+    use crate::schema::order_discounts::dsl::*;
+
+    let list = order_discounts
+        .filter(order_id.eq(oid))
+        .order((created_at.asc(), discount_id.asc()))
+        .load::<OrderDiscount>(conn)?;
+
+    Ok(list)
+}
+
+/// Codes are stored in upper case, so that they can be typed in any case.
+pub fn normalize_code(value: &str) -> String {
+    value.trim().to_ascii_uppercase()
+}
+
+fn coupon_error(message: &str) -> AppError {
+    AppError::Validation(vec![FieldError::new("coupon_code", message)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn coupon(kind: DiscountKind, percent_off: Option<i32>, amount_off_minor: Option<i64>) -> Coupon {
+        Coupon {
+            coupon_id: Uuid::new_v4(),
+            code: "TEST".to_owned(),
+            kind,
+            percent_off,
+            amount_off_minor,
+            currency: None,
+            min_order_total_minor: None,
+            expires_at: None,
+            max_uses: None,
+            max_uses_per_user: None,
+            times_used: 0,
+            active: true,
+            created_at: chrono::offset::Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn percentage_rounds_down() {
+        let ten = coupon(DiscountKind::Percentage, Some(10), None);
+        assert_eq!(ten.discount_on(Money::new(1999, Currency::Usd)), 199);
+        assert_eq!(ten.discount_on(Money::new(0, Currency::Usd)), 0);
+    }
+
+    #[test]
+    fn percentage_of_large_subtotal_does_not_overflow() {
+        let half = coupon(DiscountKind::Percentage, Some(50), None);
+        assert_eq!(half.discount_on(Money::new(i64::MAX, Currency::Usd)), i64::MAX / 2);
+
+        let all = coupon(DiscountKind::Percentage, Some(100), None);
+        assert_eq!(all.discount_on(Money::new(i64::MAX, Currency::Usd)), i64::MAX);
+    }
+
+    #[test]
+    fn fixed_is_capped_by_subtotal() {
+        let five = coupon(DiscountKind::Fixed, None, Some(500));
+        assert_eq!(five.discount_on(Money::new(300, Currency::Usd)), 300);
+        assert_eq!(five.discount_on(Money::new(3000, Currency::Usd)), 500);
+    }
+}
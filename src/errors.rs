@@ -0,0 +1,274 @@
+//! Application wide error type.
+//!
+//! Actions return `AppError` and handlers simply propagate it with `?`. It is turned into RFC 7807
+//! `application/problem+json` response. Besides standard members (`type`, `title`, `status`, `detail`, `instance`)
+//! body carries stable machine readable `code` and, for validation failures, `errors` array with per field messages
+//! (or `skus` array for stock shortage).
+//! `instance` identifies this particular occurrence and is logged along with underlying cause of server side errors,
+//! which is never sent to client.
+
+use actix_web::error::{BlockingError, JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::Serialize;
+use std::fmt;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+static PROBLEM_JSON: &str = "application/problem+json";
+
+#[derive(Debug)]
+pub enum AppError {
+    /// Request could not be parsed (e.g. malformed json).
+    BadRequest(String),
+    NotFound(String),
+    Conflict(String),
+    /// Request carries no credentials.
+    Unauthorized(String),
+    /// Request carries access token which is malformed, expired or revoked.
+    InvalidToken(String),
+    Forbidden(String),
+    /// Conditional request (If-Match) does not match current version of resource.
+    PreconditionFailed(String),
+    /// Mutating request is missing If-Match header.
+    PreconditionRequired(String),
+    /// Products (by SKU) which do not have enough stock.
+    OutOfStock(Vec<String>),
+    Validation(Vec<FieldError>),
+    Db(DieselError),
+    Internal(String),
+}
+
+/// Validation failure of single field of request payload.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> FieldError {
+        FieldError {
+            field: field.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProblemDetails<'a> {
+    #[serde(rename = "type")]
+    problem_type: String,
+    title: &'a str,
+    status: u16,
+    detail: &'a str,
+    instance: String,
+    code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    errors: Option<&'a [FieldError]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skus: Option<&'a [String]>,
+}
+
+impl AppError {
+    /// Stable identifier of error kind which clients can match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::BadRequest(_) => "bad_request",
+            AppError::NotFound(_) => "not_found",
+            AppError::Conflict(_) => "conflict",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::InvalidToken(_) => "invalid_token",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::PreconditionRequired(_) => "precondition_required",
+            AppError::OutOfStock(_) => "out_of_stock",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Db(DieselError::NotFound) => "not_found",
+            AppError::Db(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => "conflict",
+            AppError::Db(_) => "database_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    /// Message which is safe to show to client.
+    fn detail(&self) -> &str {
+        match self {
+            AppError::BadRequest(m)
+            | AppError::NotFound(m)
+            | AppError::Conflict(m)
+            | AppError::Unauthorized(m)
+            | AppError::InvalidToken(m)
+            | AppError::Forbidden(m)
+            | AppError::PreconditionFailed(m)
+            | AppError::PreconditionRequired(m) => m,
+            AppError::Validation(_) => "Request payload is not valid. See 'errors' for details.",
+            AppError::OutOfStock(_) => "Some products do not have enough stock. See 'skus' for details.",
+            AppError::Db(DieselError::NotFound) => "Requested resource could not be found.",
+            AppError::Db(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                "Resource already exists."
+            }
+            AppError::Db(_) | AppError::Internal(_) => "Something unexpected happened. Please retry",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Db(e) => write!(f, "{}: {}", self.code(), e),
+            AppError::Internal(cause) => write!(f, "{}: {}", self.code(), cause),
+            _ => write!(f, "{}: {}", self.code(), self.detail()),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict(_) | AppError::OutOfStock(_) => StatusCode::CONFLICT,
+            AppError::Unauthorized(_) | AppError::InvalidToken(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Db(DieselError::NotFound) => StatusCode::NOT_FOUND,
+            AppError::Db(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => StatusCode::CONFLICT,
+            AppError::Db(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        let instance = format!("urn:uuid:{}", uuid::Uuid::new_v4());
+
+        if status.is_server_error() {
+            log::error!("{} {}", instance, self);
+        }
+
+        let mut response = HttpResponse::build(status);
+        response.content_type(PROBLEM_JSON);
+
+        // Challenge as per RFC 6750.
+        match self {
+            AppError::Unauthorized(_) => {
+                response.header(header::WWW_AUTHENTICATE, "Bearer realm=\"api\"");
+            }
+            AppError::InvalidToken(_) => {
+                response.header(header::WWW_AUTHENTICATE, "Bearer realm=\"api\", error=\"invalid_token\"");
+            }
+            _ => {}
+        }
+
+        let errors = match self {
+            AppError::Validation(errors) => Some(errors.as_slice()),
+            _ => None,
+        };
+
+        let skus = match self {
+            AppError::OutOfStock(skus) => Some(skus.as_slice()),
+            _ => None,
+        };
+
+        let body = ProblemDetails {
+            problem_type: format!("urn:problem-type:{}", self.code()),
+            title: status.canonical_reason().unwrap_or("Error"),
+            status: status.as_u16(),
+            detail: self.detail(),
+            instance,
+            code: self.code(),
+            errors,
+            skus,
+        };
+
+        // Content type set above is kept by `json()`.
+        response.json(body)
+    }
+}
+
+impl From<DieselError> for AppError {
+    fn from(e: DieselError) -> Self {
+        AppError::Db(e)
+    }
+}
+
+impl From<diesel::r2d2::PoolError> for AppError {
+    fn from(e: diesel::r2d2::PoolError) -> Self {
+        AppError::Internal(format!("couldn't get db connection from pool: {}", e))
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(e: ValidationErrors) -> Self {
+        let mut errors = vec![];
+        collect_field_errors("", &e, &mut errors);
+        errors.sort_by(|a, b| a.field.cmp(&b.field));
+        AppError::Validation(errors)
+    }
+}
+
+/// Flatten nested validation errors. Fields of nested structs are named like `items[0].qty`.
+fn collect_field_errors(prefix: &str, e: &ValidationErrors, out: &mut Vec<FieldError>) {
+    for (field, kind) in e.errors() {
+        let path = if prefix.is_empty() {
+            field.to_string()
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errs) => out.extend(errs.iter().map(|err| FieldError {
+                field: path.clone(),
+                message: err
+                    .message
+                    .as_ref()
+                    .map(|m| m.to_string())
+                    .unwrap_or_else(|| format!("failed '{}' validation", err.code)),
+            })),
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(&path, nested, out),
+            ValidationErrorsKind::List(items) => {
+                for (idx, nested) in items {
+                    collect_field_errors(&format!("{}[{}]", path, idx), nested, out);
+                }
+            }
+        }
+    }
+}
+
+impl From<BlockingError<AppError>> for AppError {
+    fn from(e: BlockingError<AppError>) -> Self {
+        match e {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => AppError::Internal("blocking operation was canceled".to_owned()),
+        }
+    }
+}
+
+/// Used as `JsonConfig` error handler so that malformed request bodies are answered with problem details too.
+pub fn json_error_handler(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Deserialize(e) => AppError::Validation(vec![FieldError::new("body", &e.to_string())]),
+        e => AppError::BadRequest(e.to_string()),
+    }
+    .into()
+}
+
+/// Used as `QueryConfig` error handler so that invalid query parameters are answered with problem details too.
+pub fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        QueryPayloadError::Deserialize(e) => AppError::Validation(vec![FieldError::new("query", &e.to_string())]),
+    }
+    .into()
+}
+
+/// Used as `PathConfig` error handler. Path which can not be parsed (e.g. invalid uuid) can not point to any resource.
+pub fn path_error_handler(_err: PathError, req: &HttpRequest) -> actix_web::Error {
+    AppError::NotFound(format!("Resource '{}' could not be found.", req.path())).into()
+}
+
+/// Used as default service for requests which do not match any route.
+pub async fn route_not_found(req: HttpRequest) -> Result<HttpResponse, AppError> {
+    Err(AppError::NotFound(format!("Resource '{}' could not be found.", req.path())))
+}
@@ -0,0 +1,179 @@
+//! Stock of products.
+//!
+//! Available quantity is kept in `products.stock_available`. Every change of it goes through `apply_stock_changes`,
+//! which takes stock only when enough is available and records each change in `stock_adjustments` as audit trail.
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use uuid::Uuid;
+
+use crate::errors::{AppError, FieldError};
+use crate::schema::stock_adjustments;
+
+/// Why stock of product changed. Stored as lowercase string in `stock_adjustments.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Varchar"]
+pub enum StockChangeKind {
+    /// Stock taken by order.
+    Reservation,
+    /// Stock given back by order, e.g. when it is cancelled or its items are removed.
+    Release,
+    /// Manual change by admin, e.g. new delivery or stock take.
+    Adjustment,
+}
+
+impl StockChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StockChangeKind::Reservation => "reservation",
+            StockChangeKind::Release => "release",
+            StockChangeKind::Adjustment => "adjustment",
+        }
+    }
+}
+
+impl ToSql<Varchar, Pg> for StockChangeKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Varchar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Pg> for StockChangeKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let value: String = FromSql::<Varchar, Pg>::from_sql(bytes)?;
+        match value.as_str() {
+            "reservation" => Ok(StockChangeKind::Reservation),
+            "release" => Ok(StockChangeKind::Release),
+            "adjustment" => Ok(StockChangeKind::Adjustment),
+            other => Err(format!("unknown stock change kind '{}'", other).into()),
+        }
+    }
+}
+
+/// Audit trail entry of single change of stock.
+#[derive(Debug, Clone, Serialize, Queryable, Insertable)]
+pub struct StockAdjustment {
+    pub adjustment_id: Uuid,
+    pub product_id: Uuid,
+    pub delta: i32,
+    // Available quantity right after the change.
+    pub stock_after: i32,
+    pub kind: StockChangeKind,
+    pub order_id: Option<Uuid>,
+    // User who made the change, i.e. customer for order changes and admin for manual ones.
+    pub user_id: Uuid,
+    pub reason: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Change of available quantity of product, negative to take stock.
+#[derive(Debug, Clone, Copy)]
+pub struct StockChange {
+    pub product_id: Uuid,
+    pub delta: i32,
+}
+
+/// Apply stock changes made by `user_id_arg`, for order `order_id_arg` or manually by admin when it is `None`.
+/// When any product does not have enough stock nothing is recorded and `AppError::OutOfStock` listing SKUs of all
+/// such products is returned. Change which would take stock past what the column can hold is rejected with 422, on
+/// `delta` for manual changes and on `items` for orders. Must be called in transaction, so that changes already
+/// applied are rolled back then.
+pub fn apply_stock_changes(
+    changes: &[StockChange],
+    order_id_arg: Option<Uuid>,
+    user_id_arg: Uuid,
+    reason_arg: Option<&str>,
+    conn: &PgConnection,
+) -> Result<(), AppError> {
+    use crate::schema::products::dsl::*;
+
+    // Same product may come in several changes. Merge them and go in product_id order, so that concurrent requests
+    // lock product rows in same order and can not deadlock.
+    let mut merged: BTreeMap<Uuid, i32> = BTreeMap::new();
+    for change in changes {
+        let delta = merged.entry(change.product_id).or_insert(0);
+        *delta = delta.checked_add(change.delta).ok_or_else(|| {
+            AppError::Validation(vec![FieldError::new("qty", "total quantity of product is too large")])
+        })?;
+    }
+
+    let now = chrono::offset::Utc::now().naive_utc();
+    let mut records: Vec<StockAdjustment> = vec![];
+    let mut out_of_stock: Vec<Uuid> = vec![];
+    let mut too_large = false;
+
+    for (pid, delta) in merged.into_iter().filter(|(_, delta)| *delta != 0) {
+        // Conditional update, row is locked and checked against both bounds in single statement.
+        let max_before = if delta > 0 { i32::MAX - delta } else { i32::MAX };
+        let target = products
+            .filter(product_id.eq(pid))
+            .filter(stock_available.ge(-delta))
+            .filter(stock_available.le(max_before));
+        let stock_after = diesel::update(target)
+            .set(stock_available.eq(stock_available + delta))
+            .returning(stock_available)
+            .get_result::<i32>(conn)
+            .optional()?;
+
+        match stock_after {
+            Some(after) => records.push(StockAdjustment {
+                adjustment_id: Uuid::new_v4(),
+                product_id: pid,
+                delta,
+                stock_after: after,
+                kind: match order_id_arg {
+                    None => StockChangeKind::Adjustment,
+                    Some(_) if delta < 0 => StockChangeKind::Reservation,
+                    Some(_) => StockChangeKind::Release,
+                },
+                order_id: order_id_arg,
+                user_id: user_id_arg,
+                reason: reason_arg.map(str::to_owned),
+                created_at: now,
+            }),
+            None if delta > 0 => too_large = true,
+            None => out_of_stock.push(pid),
+        }
+    }
+
+    if too_large {
+        let field = if order_id_arg.is_some() { "items" } else { "delta" };
+        return Err(AppError::Validation(vec![FieldError::new(field, "stock of product would be too large")]));
+    }
+
+    if !out_of_stock.is_empty() {
+        let skus = products
+            .select(sku)
+            .filter(product_id.eq_any(&out_of_stock))
+            .order(sku.asc())
+            .load::<String>(conn)?;
+        return Err(AppError::OutOfStock(skus));
+    }
+
+    if !records.is_empty() {
+        diesel::insert_into(stock_adjustments::table)
+            .values(&records)
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Audit trail of stock of product, latest change first.
+pub fn find_stock_adjustments(pid: Uuid, conn: &PgConnection) -> Result<Vec<StockAdjustment>, AppError> {
+    use crate::schema::stock_adjustments::dsl::*;
+
+    let list = stock_adjustments
+        .filter(product_id.eq(pid))
+        .order((created_at.desc(), adjustment_id.desc()))
+        .load::<StockAdjustment>(conn)?;
+
+    Ok(list)
+}
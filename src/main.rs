@@ -1,24 +1,49 @@
 #[macro_use]
 extern crate diesel;
 
-use actix_web::{ middleware, App,  HttpServer};
+use actix_web::{ middleware, web, App,  HttpServer};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
+mod config;
+mod discounts;
+mod errors;
+mod inventory;
+mod money;
+mod payload;
+mod preconditions;
 mod schema;
 
+mod addresses {
+    pub mod address_handlers;
+}
+
+mod cart {
+    pub mod cart_handlers;
+}
+
+mod coupons {
+    pub mod coupon_handlers;
+}
+
 mod orders {
     pub mod order_handlers;
 }
 
+mod products {
+    pub mod product_handlers;
+}
+
 mod users {
+    pub mod auth;
+    pub mod revocation;
     pub mod user_handlers;
 }
 
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    std::env::set_var("RUST_LOG", "actix_web=info");
+    std::env::set_var("RUST_LOG", "actix_web=info,actix_diesel_ecommerce_demo=info");
     env_logger::init();
     dotenv::dotenv().ok();
 
@@ -29,6 +54,15 @@ async fn main() -> std::io::Result<()> {
         .build(manager)
         .expect("Failed to create pool.");
 
+    // load jwt signing keys once at startup
+    let jwt_keys = config::JwtKeys::from_env().expect("Failed to load jwt keys");
+    let password_hashing = config::PasswordHashing::from_env().expect("Failed to load password hashing settings");
+    let token_header_precedence =
+        config::TokenHeaderPrecedence::from_env().expect("Failed to load auth token header precedence");
+
+    // revoked token cache is shared by all workers
+    let revocations = web::Data::new(users::revocation::RevocationStore::default());
+
     let bind = "127.0.0.1:8080";
 
     println!("Starting server at: {}", &bind);
@@ -38,12 +72,47 @@ async fn main() -> std::io::Result<()> {
         App::new()
             // set up DB pool to be used with web::Data<Pool> extractor
             .data(pool.clone())
+            .data(jwt_keys.clone())
+            .data(password_hashing.clone())
+            .data(token_header_precedence)
+            .app_data(revocations.clone())
             .wrap(middleware::Logger::default())
+            // answer extractor failures with problem details like any other error
+            .app_data(web::JsonConfig::default().error_handler(errors::json_error_handler))
+            .app_data(web::PathConfig::default().error_handler(errors::path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(errors::query_error_handler))
             .service(users::user_handlers::register_user)
             .service(users::user_handlers::login_user)
+            .service(users::user_handlers::refresh_access_token)
+            .service(users::user_handlers::logout_user)
+            .service(users::user_handlers::logout_all_sessions)
             .service(orders::order_handlers::get_order_by_id)
             .service(orders::order_handlers::create_order)
             .service(orders::order_handlers::get_order_details_for_user)
+            .service(orders::order_handlers::update_order)
+            .service(orders::order_handlers::update_order_status)
+            .service(orders::order_handlers::cancel_order)
+            .service(products::product_handlers::get_products)
+            .service(products::product_handlers::get_product_by_id)
+            .service(products::product_handlers::create_product)
+            .service(products::product_handlers::update_product)
+            .service(products::product_handlers::adjust_product_stock)
+            .service(products::product_handlers::get_product_stock_adjustments)
+            .service(addresses::address_handlers::get_addresses)
+            .service(addresses::address_handlers::get_address_by_id)
+            .service(addresses::address_handlers::create_address)
+            .service(addresses::address_handlers::replace_address)
+            .service(addresses::address_handlers::delete_address)
+            .service(coupons::coupon_handlers::get_coupons)
+            .service(coupons::coupon_handlers::get_coupon_by_id)
+            .service(coupons::coupon_handlers::create_coupon)
+            .service(coupons::coupon_handlers::update_coupon)
+            .service(cart::cart_handlers::get_cart)
+            .service(cart::cart_handlers::add_cart_item)
+            .service(cart::cart_handlers::update_cart_item)
+            .service(cart::cart_handlers::remove_cart_item)
+            .service(cart::cart_handlers::checkout)
+            .default_service(web::route().to(errors::route_not_found))
     })
     .bind(&bind)?
     .run()
@@ -0,0 +1,232 @@
+//! Money amounts. Amounts are kept as integer count of minor units (e.g. cents) along with ISO 4217 currency, so
+//! that no floating point is involved anywhere. In json money is `{"amount": "12.34", "currency": "USD"}` where
+//! amount is decimal string, as json numbers are read as floats by javascript clients.
+
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io::Write;
+
+/// Supported currencies. Stored as ISO 4217 code in `currency` columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "UPPERCASE")]
+#[sql_type = "Varchar"]
+pub enum Currency {
+    Eur,
+    Gbp,
+    Inr,
+    Jpy,
+    Usd,
+}
+
+impl Currency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Inr => "INR",
+            Currency::Jpy => "JPY",
+            Currency::Usd => "USD",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Currency> {
+        match code {
+            "EUR" => Some(Currency::Eur),
+            "GBP" => Some(Currency::Gbp),
+            "INR" => Some(Currency::Inr),
+            "JPY" => Some(Currency::Jpy),
+            "USD" => Some(Currency::Usd),
+            _ => None,
+        }
+    }
+
+    /// Number of digits after decimal point, i.e. minor units per major unit is 10^exponent.
+    pub fn exponent(&self) -> u32 {
+        match self {
+            Currency::Jpy => 0,
+            _ => 2,
+        }
+    }
+}
+
+impl ToSql<Varchar, Pg> for Currency {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Varchar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Pg> for Currency {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        let code: String = FromSql::<Varchar, Pg>::from_sql(bytes)?;
+        Currency::from_code(&code).ok_or_else(|| format!("unknown currency '{}'", code).into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Money {
+    pub amount_minor: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount_minor: i64, currency: Currency) -> Money {
+        Money { amount_minor, currency }
+    }
+
+    /// Parse decimal amount like "12.34". More fraction digits than currency has are rejected instead of rounded.
+    pub fn parse(amount: &str, currency: Currency) -> Result<Money, String> {
+        let (negative, digits) = match amount.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, amount),
+        };
+        let (major, minor) = match digits.split_once('.') {
+            Some((major, minor)) => (major, minor),
+            None => (digits, ""),
+        };
+
+        let exponent = currency.exponent() as usize;
+        let all_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if major.is_empty() || !all_digits(major) || !all_digits(minor) || (digits.contains('.') && minor.is_empty()) {
+            return Err(format!("'{}' is not a valid amount", amount));
+        }
+        if minor.len() > exponent {
+            return Err(format!("{} allows at most {} decimal places", currency.as_str(), exponent));
+        }
+
+        let scale = 10i64.pow(currency.exponent());
+        let minor_value = format!("{:0<width$}", minor, width = exponent);
+        let amount_minor = major
+            .parse::<i64>()
+            .ok()
+            .and_then(|major| major.checked_mul(scale))
+            .and_then(|major| major.checked_add(minor_value.parse::<i64>().unwrap_or(0)))
+            .ok_or_else(|| format!("'{}' is too large", amount))?;
+
+        Ok(Money::new(if negative { -amount_minor } else { amount_minor }, currency))
+    }
+
+    /// Amount of `qty` pieces priced at this amount, `None` when it does not fit in i64.
+    pub fn checked_mul(&self, qty: i32) -> Option<Money> {
+        self.amount_minor.checked_mul(i64::from(qty)).map(|amount_minor| Money::new(amount_minor, self.currency))
+    }
+
+    /// Amount as decimal string, e.g. "12.34".
+    pub fn amount(&self) -> String {
+        let exponent = self.currency.exponent();
+        if exponent == 0 {
+            return self.amount_minor.to_string();
+        }
+
+        let scale = 10u64.pow(exponent);
+        let abs = self.amount_minor.unsigned_abs();
+        format!(
+            "{}{}.{:0width$}",
+            if self.amount_minor < 0 { "-" } else { "" },
+            abs / scale,
+            abs % scale,
+            width = exponent as usize
+        )
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount(), self.currency.as_str())
+    }
+}
+
+/// Json representation of money.
+#[derive(Serialize, Deserialize)]
+struct MoneyJson {
+    amount: String,
+    currency: Currency,
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MoneyJson {
+            amount: self.amount(),
+            currency: self.currency,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let json = MoneyJson::deserialize(deserializer)?;
+        Money::parse(&json.amount, json.currency).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_amount_round_trip() {
+        let cases = [
+            ("0.00", Currency::Usd, 0),
+            ("12.34", Currency::Usd, 1234),
+            ("0.05", Currency::Eur, 5),
+            ("-0.05", Currency::Gbp, -5),
+            ("-12.30", Currency::Inr, -1230),
+            ("1500", Currency::Jpy, 1500),
+            ("-7", Currency::Jpy, -7),
+            ("92233720368547758.07", Currency::Usd, i64::MAX),
+            ("9223372036854775807", Currency::Jpy, i64::MAX),
+        ];
+
+        for (amount, currency, amount_minor) in cases {
+            let money = Money::parse(amount, currency).unwrap();
+            assert_eq!(money, Money::new(amount_minor, currency), "{}", amount);
+            assert_eq!(money.amount(), amount);
+        }
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let money = Money::new(-1234, Currency::Eur);
+        let json = serde_json::to_string(&money).unwrap();
+        assert_eq!(json, r#"{"amount":"-12.34","currency":"EUR"}"#);
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), money);
+
+        assert!(serde_json::from_str::<Money>(r#"{"amount":"1.005","currency":"USD"}"#).is_err());
+        assert!(serde_json::from_str::<Money>(r#"{"amount":"1.00","currency":"XYZ"}"#).is_err());
+    }
+
+    #[test]
+    fn parse_pads_missing_fraction_digits() {
+        assert_eq!(Money::parse("12", Currency::Usd), Ok(Money::new(1200, Currency::Usd)));
+        assert_eq!(Money::parse("12.3", Currency::Usd), Ok(Money::new(1230, Currency::Usd)));
+        assert_eq!(Money::new(1230, Currency::Usd).amount(), "12.30");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_amounts() {
+        for amount in ["", "-", "+1.00", "--1", "1.", ".50", "1.2.3", "1,00", " 1.00", "1e3", "abc"] {
+            assert!(Money::parse(amount, Currency::Usd).is_err(), "'{}' should be rejected", amount);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_too_many_fraction_digits() {
+        assert_eq!(Money::parse("1.005", Currency::Usd), Err("USD allows at most 2 decimal places".to_owned()));
+        assert_eq!(Money::parse("1.5", Currency::Jpy), Err("JPY allows at most 0 decimal places".to_owned()));
+        assert!(Money::parse("1.0", Currency::Jpy).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_overflow() {
+        assert!(Money::parse("92233720368547758.08", Currency::Usd).is_err());
+        assert!(Money::parse("92233720368547759", Currency::Usd).is_err());
+        assert!(Money::parse("-92233720368547759", Currency::Usd).is_err());
+        assert!(Money::parse("9223372036854775808", Currency::Jpy).is_err());
+        assert!(Money::parse("99999999999999999999999", Currency::Jpy).is_err());
+    }
+}
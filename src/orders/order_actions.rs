@@ -1,122 +1,889 @@
 #[path = "./order_models.rs"] pub mod models;
 
-use actix_web::http::{ StatusCode};
+use diesel::expression::SqlLiteral;
 use diesel::prelude::*;
-use models::NewOrderItem;
-use models::{Order, OrderDetails, OrderItem, OrderItemDetails};
+use diesel::sql_types::BigInt;
 use std::collections::HashMap;
+use models::{AddressKind, NewOrderItem, OrderAddress, OrderAddressDetails, OrderDiscountDetails, PricedOrderItem};
+use models::{IdempotencyKey, Order, OrderDetails, OrderItem, OrderItemDetails};
+use models::{OrderCancellation, OrderPage, OrderSort, OrderStatus, OrderStatusChange, OrderStatusChangeDetails};
 use uuid::Uuid;
 
+use crate::discounts;
+use crate::errors::{AppError, FieldError};
+use crate::inventory::{self, StockChange};
+use crate::money::{Currency, Money};
+use crate::preconditions::VersionPrecondition;
 
+// Stored responses are replayed for this long, after that key can be used again.
+static IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
 
+/// Allowed order status transitions as (from, to). Anything not listed here is rejected.
+static ORDER_STATUS_TRANSITIONS: &[(OrderStatus, OrderStatus)] = &[
+    (OrderStatus::Pending, OrderStatus::Paid),
+    (OrderStatus::Pending, OrderStatus::Cancelled),
+    (OrderStatus::Paid, OrderStatus::Fulfilled),
+    (OrderStatus::Paid, OrderStatus::Cancelled),
+    (OrderStatus::Paid, OrderStatus::Refunded),
+    (OrderStatus::Fulfilled, OrderStatus::Shipped),
+    (OrderStatus::Fulfilled, OrderStatus::Cancelled),
+    (OrderStatus::Fulfilled, OrderStatus::Refunded),
+    (OrderStatus::Shipped, OrderStatus::Delivered),
+    (OrderStatus::Delivered, OrderStatus::Refunded),
+];
 
-/// Find order corresponding to given user_id and order_id.
-pub fn find_order_by_id(user_id_arg: Uuid, oid: Uuid, conn: &PgConnection) -> Result<OrderDetails, StatusCode> {
+/// Statuses in which customer can still cancel the order, i.e. before it is shipped.
+static CUSTOMER_CANCELLABLE_STATUSES: &[OrderStatus] = &[OrderStatus::Pending, OrderStatus::Paid, OrderStatus::Fulfilled];
+
+/// Statuses in which note and items of order can still be edited, i.e. before it is fulfilled.
+static EDITABLE_STATUSES: &[OrderStatus] = &[OrderStatus::Pending, OrderStatus::Paid];
+
+pub static MAX_ITEMS_PER_ORDER: usize = 100;
+
+// Same as upper limit of `qty` in validators.
+pub static MAX_ITEM_QTY: i32 = 10000;
+
+/// Page size of order list when client does not give `limit`.
+static DEFAULT_PAGE_SIZE: i64 = 20;
+
+// Macro rather than static, so that total below can be built from it with `concat!`.
+macro_rules! order_subtotal_sql_text {
+    () => {
+        "(SELECT COALESCE(SUM(order_items.qty * order_items.price_minor), 0)::bigint \
+         FROM order_items WHERE order_items.order_id = orders.order_id)"
+    };
+}
+
+/// Sum of items of order in minor units computed in db, before discounts.
+static ORDER_SUBTOTAL_SQL: &str = order_subtotal_sql_text!();
+
+/// Total of order in minor units computed in db, i.e. subtotal less discounts. Used by both detail and list views so
+/// that they always agree, and lets list be sorted and paged by total.
+static ORDER_TOTAL_SQL: &str = concat!(
+    "(",
+    order_subtotal_sql_text!(),
+    " - (SELECT COALESCE(SUM(order_discounts.amount_minor), 0)::bigint \
+     FROM order_discounts WHERE order_discounts.order_id = orders.order_id))"
+);
+
+
+
+/// Find order corresponding to given user_id and order_id. Order which has no items is returned with empty items.
+pub fn find_order_by_id(user_id_arg: Uuid, oid: Uuid, conn: &PgConnection) -> Result<OrderDetails, AppError> {
     // It is common when using Diesel with Actix web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
     use crate::schema::orders::dsl::*;
-    use crate::schema::order_items::dsl::*;
 
-    let vec: Vec<(Order, OrderItem)> = orders
-        .inner_join(order_items)
+    let (order, subtotal, total) = orders
+        .select((crate::schema::orders::all_columns, order_subtotal_sql(), order_total_sql()))
         // Only allow to access user’s own order not others
-        .filter(crate::schema::orders::dsl::user_id.eq(user_id_arg))
-        .filter(crate::schema::orders::dsl::order_id.eq(oid))
-        .into_boxed()
-        .get_results(conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .filter(user_id.eq(user_id_arg))
+        .filter(order_id.eq(oid))
+        .first::<(Order, i64, i64)>(conn)
+        .optional()?
+        .ok_or_else(|| {
+            AppError::NotFound("Order id not correct(or not present) for the user in access_token.".to_owned())
+        })?;
+
+    let items = find_items_of_orders(std::slice::from_ref(&order), conn)?.pop().unwrap_or_default();
 
-    // If vec is empty. Then return not found error.
-    if vec.len() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+    let order_currency = order.currency;
+    let mut ret_value = order_details(order, subtotal, total, Some(items));
+    ret_value.status_history = Some(find_status_history(oid, conn)?);
+    ret_value.discounts = Some(
+        discounts::find_order_discounts(oid, conn)?
+            .into_iter()
+            .map(|discount| OrderDiscountDetails {
+                coupon_code: discount.code,
+                amount: Money::new(discount.amount_minor, order_currency),
+            })
+            .collect(),
+    );
+
+    for address in find_order_addresses(oid, conn)? {
+        match address.kind {
+            AddressKind::Shipping => ret_value.shipping_address = Some(address.into()),
+            AddressKind::Billing => ret_value.billing_address = Some(address.into()),
+        }
     }
 
-    // Order is common for all tuples in vector. Hence taking first one.
-    let order = vec[0].0.clone();
+    Ok(ret_value)
+}
+
+/// Find one page of orders for a user_id (from jwt). Orders are paged by keyset on (sort key, order_id), so that
+/// deep pages are as cheap as first one and orders created meanwhile do not shift pages.
+pub fn find_all_orders_for_user(
+    uid: Uuid,
+    params: &models::OrderListQuery,
+    conn: &PgConnection,
+) -> Result<OrderPage, AppError> {
+    use crate::schema::orders::dsl::*;
 
-    let mut ret_value: OrderDetails = OrderDetails {
+    let page_size = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    let mut query = orders
+        .select((crate::schema::orders::all_columns, order_subtotal_sql(), order_total_sql()))
+        .filter(user_id.eq(uid))
+        .into_boxed();
+
+    if let Some(from) = params.from {
+        query = query.filter(created_at.ge(from.naive_utc()));
+    }
+    if let Some(to) = params.to {
+        query = query.filter(created_at.lt(to.naive_utc()));
+    }
+
+    // Continue right after last order of previous page.
+    if let Some(cursor) = &params.cursor {
+        let (key, last_id) = decode_cursor(cursor, params.sort)?;
+        query = match params.sort {
+            OrderSort::CreatedAt | OrderSort::CreatedAtDesc => {
+                let ts = chrono::DateTime::from_timestamp_micros(key)
+                    .map(|dt| dt.naive_utc())
+                    .ok_or_else(invalid_cursor)?;
+                if params.sort == OrderSort::CreatedAt {
+                    query.filter(created_at.gt(ts).or(created_at.eq(ts).and(order_id.gt(last_id))))
+                } else {
+                    query.filter(created_at.lt(ts).or(created_at.eq(ts).and(order_id.lt(last_id))))
+                }
+            }
+            OrderSort::Total => query.filter(
+                order_total_sql()
+                    .gt(key)
+                    .or(order_total_sql().eq(key).and(order_id.gt(last_id))),
+            ),
+            OrderSort::TotalDesc => query.filter(
+                order_total_sql()
+                    .lt(key)
+                    .or(order_total_sql().eq(key).and(order_id.lt(last_id))),
+            ),
+        };
+    }
+
+    query = match params.sort {
+        OrderSort::CreatedAt => query.order((created_at.asc(), order_id.asc())),
+        OrderSort::CreatedAtDesc => query.order((created_at.desc(), order_id.desc())),
+        OrderSort::Total => query.order((order_total_sql().asc(), order_id.asc())),
+        OrderSort::TotalDesc => query.order((order_total_sql().desc(), order_id.desc())),
+    };
+
+    // One extra row tells whether there is next page.
+    let mut rows: Vec<(Order, i64, i64)> = query.limit(page_size + 1).load(conn)?;
+    let has_more = rows.len() as i64 > page_size;
+    rows.truncate(page_size as usize);
+
+    let next_cursor = match rows.last() {
+        Some((order, _, order_total)) if has_more => Some(encode_cursor(params.sort, order, *order_total)),
+        _ => None,
+    };
+
+    let totals: Vec<(i64, i64)> = rows.iter().map(|(_, subtotal, order_total)| (*subtotal, *order_total)).collect();
+    let page_orders: Vec<Order> = rows.into_iter().map(|(order, _, _)| order).collect();
+
+    // Items of all orders in page are loaded with single query.
+    let items: Vec<Option<Vec<OrderItem>>> = if params.include_items() {
+        find_items_of_orders(&page_orders, conn)?.into_iter().map(Some).collect()
+    } else {
+        vec![None; page_orders.len()]
+    };
+
+    let page = page_orders
+        .into_iter()
+        .zip(totals)
+        .zip(items)
+        .map(|((order, (subtotal, order_total)), order_items)| order_details(order, subtotal, order_total, order_items))
+        .collect();
+
+    Ok(OrderPage {
+        orders: page,
+        next_cursor,
+    })
+}
+
+/// Snapshot current name and price of products of new items. Items of unknown or inactive products, and of
+/// products priced in other currency than `order_currency` (or than first item when it is `None`), are reported in
+/// `errors` under `field` and left out of returned items.
+fn price_new_items(
+    items: &[NewOrderItem],
+    field: &str,
+    order_currency: Option<Currency>,
+    errors: &mut Vec<FieldError>,
+    conn: &PgConnection,
+) -> Result<Vec<PricedOrderItem>, AppError> {
+    use crate::schema::products::dsl::*;
+
+    let ids: Vec<Uuid> = items.iter().map(|item| item.product_id).collect();
+
+    let available: HashMap<Uuid, (String, i64, Currency)> = products
+        .select((product_id, name, price_minor, currency))
+        .filter(product_id.eq_any(&ids))
+        .filter(active.eq(true))
+        .load::<(Uuid, String, i64, Currency)>(conn)?
+        .into_iter()
+        .map(|(id, product_name, price, product_currency)| (id, (product_name, price, product_currency)))
+        .collect();
+
+    let mut expected_currency = order_currency;
+    let mut priced = vec![];
+
+    for (idx, item) in items.iter().enumerate() {
+        let (product_name, price, product_currency) = match available.get(&item.product_id) {
+            Some(product) => product,
+            None => {
+                errors.push(FieldError::new(&format!("{}[{}].product_id", field, idx), "is not an available product"));
+                continue;
+            }
+        };
+
+        let expected = *expected_currency.get_or_insert(*product_currency);
+        if *product_currency != expected {
+            errors.push(FieldError::new(
+                &format!("{}[{}].product_id", field, idx),
+                &format!("is priced in {} while order is in {}", product_currency.as_str(), expected.as_str()),
+            ));
+            continue;
+        }
+
+        priced.push(PricedOrderItem {
+            product_id: item.product_id,
+            description: product_name.clone(),
+            qty: item.qty,
+            price: Money::new(*price, *product_currency),
+        });
+    }
+
+    Ok(priced)
+}
+
+/// Load items of given orders with single query. Items are grouped per order, in same order as `parents`, and
+/// orders without items get empty group.
+fn find_items_of_orders(parents: &[Order], conn: &PgConnection) -> Result<Vec<Vec<OrderItem>>, AppError> {
+    use crate::schema::order_items::dsl::*;
+
+    let items = OrderItem::belonging_to(parents)
+        .order((created_at.asc(), item_id.asc()))
+        .load::<OrderItem>(conn)?;
+
+    Ok(items.grouped_by(parents))
+}
+
+/// Copy of address `aid` from address book of user, to be stored with order. Address which is not in address book
+/// of user is reported in `errors` under `field`.
+fn find_address_snapshot(
+    uid: Uuid,
+    aid: Option<Uuid>,
+    field: &str,
+    errors: &mut Vec<FieldError>,
+    conn: &PgConnection,
+) -> Result<Option<OrderAddressDetails>, AppError> {
+    use crate::schema::addresses::dsl::*;
+
+    let aid = match aid {
+        Some(aid) => aid,
+        None => return Ok(None),
+    };
+
+    let address = addresses
+        .select((full_name, line1, line2, city, region, postal_code, country, phone))
+        // Only allow to use user’s own address not others
+        .filter(user_id.eq(uid))
+        .filter(address_id.eq(aid))
+        .first::<OrderAddressDetails>(conn)
+        .optional()?;
+
+    if address.is_none() {
+        errors.push(FieldError::new(field, "is not an address of this user"));
+    }
+
+    Ok(address)
+}
+
+fn insert_order_addresses(
+    oid: Uuid,
+    shipping: Option<OrderAddressDetails>,
+    billing: Option<OrderAddressDetails>,
+    conn: &PgConnection,
+) -> Result<(), AppError> {
+    use crate::schema::order_addresses::dsl::*;
+
+    let records: Vec<OrderAddress> = vec![(AddressKind::Shipping, shipping), (AddressKind::Billing, billing)]
+        .into_iter()
+        .filter_map(|(address_kind, address)| address.map(|address| OrderAddress::new(oid, address_kind, address)))
+        .collect();
+
+    if !records.is_empty() {
+        diesel::insert_into(order_addresses)
+            .values(&records)
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+fn find_order_addresses(oid: Uuid, conn: &PgConnection) -> Result<Vec<OrderAddress>, AppError> {
+    use crate::schema::order_addresses::dsl::*;
+
+    let list = order_addresses
+        .filter(order_id.eq(oid))
+        .load::<OrderAddress>(conn)?;
+
+    Ok(list)
+}
+
+/// Expression selecting subtotal of order, to be selected along with order columns.
+fn order_subtotal_sql() -> SqlLiteral<BigInt> {
+    diesel::dsl::sql::<BigInt>(ORDER_SUBTOTAL_SQL)
+}
+
+/// Sum of line totals of order, `None` when it does not fit in i64.
+fn checked_subtotal(mut lines: impl Iterator<Item = (Money, i32)>) -> Option<i64> {
+    lines.try_fold(0i64, |sum, (price, qty)| sum.checked_add(price.checked_mul(qty)?.amount_minor))
+}
+
+fn order_too_large() -> AppError {
+    AppError::Validation(vec![FieldError::new("items", "total amount of order is too large")])
+}
+
+/// Expression selecting total of order, to be selected along with order columns.
+fn order_total_sql() -> SqlLiteral<BigInt> {
+    diesel::dsl::sql::<BigInt>(ORDER_TOTAL_SQL)
+}
+
+/// Read model of order shared by detail and list views. Subtotal and total are in minor units of order currency.
+/// Items are left out when `None`.
+fn order_details(order: Order, subtotal: i64, order_total: i64, items: Option<Vec<OrderItem>>) -> OrderDetails {
+    OrderDetails {
+        cancellation: cancellation_of(&order),
+        cancelled: order.status == OrderStatus::Cancelled,
         order_id: order.order_id,
         user_id: order.user_id,
         note: order.note,
-        order_total: 0,
+        subtotal: Money::new(subtotal, order.currency),
+        order_total: Money::new(order_total, order.currency),
         order_at: order.created_at,
-        // Mark items as None initially. This will be set to below again.
-        items: None, //vec![]
+        status: order.status,
+        version: order.version,
+        items: items.map(|items| {
+            items
+                .into_iter()
+                .map(|item| OrderItemDetails {
+                    price: item.price(),
+                    product_id: item.product_id,
+                    item_id: item.item_id,
+                    description: item.description,
+                    qty: item.qty,
+                })
+                .collect()
+        }),
+        status_history: None,
+        shipping_address: None,
+        billing_address: None,
+        discounts: None,
+    }
+}
+
+/// Cursor is hex encoded "sort|key|order_id" of last order of page. Key is creation time in microseconds or total.
+fn encode_cursor(sort: OrderSort, order: &Order, order_total: i64) -> String {
+    let key = match sort {
+        OrderSort::CreatedAt | OrderSort::CreatedAtDesc => order.created_at.and_utc().timestamp_micros(),
+        OrderSort::Total | OrderSort::TotalDesc => order_total,
     };
 
-    let mut order_total: i64 = 0;
-    let mut order_item_details_vec: Vec<OrderItemDetails> = vec![];
+    hex::encode(format!("{}|{}|{}", sort.as_str(), key, order.order_id))
+}
 
-    // Iterate over all tuples and calcuate order_total. Also collect orter_items.
-    vec.iter().for_each(|tup| {
-        let order_item = tup.1.clone();
-        order_total = order_total + i64::from(order_item.qty * order_item.price);
+/// Read back key and order_id from cursor. Cursor issued for other sort order is rejected.
+fn decode_cursor(cursor: &str, sort: OrderSort) -> Result<(i64, Uuid), AppError> {
+    let decoded = hex::decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(invalid_cursor)?;
 
-        order_item_details_vec.push(OrderItemDetails {
-            item_id: order_item.item_id,
-            description: order_item.description,
-            qty: order_item.qty,
-            price: order_item.price,
-        });
-    });
+    let mut parts = decoded.splitn(3, '|');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(cursor_sort), Some(key), Some(oid)) if cursor_sort == sort.as_str() => {
+            let key = key.parse::<i64>().map_err(|_| invalid_cursor())?;
+            let oid = Uuid::parse_str(oid).map_err(|_| invalid_cursor())?;
+            Ok((key, oid))
+        }
+        _ => Err(invalid_cursor()),
+    }
+}
 
-    ret_value.order_total = order_total;
-    ret_value.items = Some(order_item_details_vec);
+fn invalid_cursor() -> AppError {
+    AppError::Validation(vec![FieldError::new(
+        "cursor",
+        "is not valid or was issued for different sort order",
+    )])
+}
 
-    Ok(ret_value)
+/// Create order along with its items in single transaction, so that failure while inserting items does not leave
+/// order without items behind. Returns details of created order.
+pub fn create_order_with_items(
+    order_id_arg: Uuid,
+    user_id_arg: Uuid,
+    new_order: &models::NewOrder,
+    conn: &PgConnection,
+) -> Result<OrderDetails, AppError> {
+    conn.transaction::<_, AppError, _>(|| {
+        let mut errors: Vec<FieldError> = vec![];
+        let items = price_new_items(&new_order.items, "items", None, &mut errors, conn)?;
+
+        let shipping_id = new_order.shipping_address_id;
+        let shipping_address = find_address_snapshot(user_id_arg, shipping_id, "shipping_address_id", &mut errors, conn)?;
+        let billing_address = match new_order.billing_address_id {
+            Some(billing_id) => {
+                find_address_snapshot(user_id_arg, Some(billing_id), "billing_address_id", &mut errors, conn)?
+            }
+            None => shipping_address.clone(),
+        };
+
+        // Order takes currency of its items, which are all in same currency once there are no errors.
+        let currency_arg = match items.first() {
+            Some(item) if errors.is_empty() => item.price.currency,
+            _ => return Err(AppError::Validation(errors)),
+        };
+
+        let subtotal = checked_subtotal(items.iter().map(|item| (item.price, item.qty))).ok_or_else(order_too_large)?;
+
+        insert_new_order(order_id_arg, user_id_arg, new_order.note.clone(), currency_arg, conn)?;
+        insert_new_order_items(order_id_arg, &items, conn)?;
+        insert_order_addresses(order_id_arg, shipping_address, billing_address, conn)?;
+
+        if let Some(code) = &new_order.coupon_code {
+            discounts::redeem_coupon(code, order_id_arg, user_id_arg, Money::new(subtotal, currency_arg), conn)?;
+        }
+
+        // Take stock of ordered products. When any of them is short, whole order is rolled back with 409.
+        let reservations: Vec<StockChange> = items
+            .iter()
+            .map(|item| StockChange {
+                product_id: item.product_id,
+                delta: -item.qty,
+            })
+            .collect();
+        inventory::apply_stock_changes(&reservations, Some(order_id_arg), user_id_arg, None, conn)?;
+
+        find_order_by_id(user_id_arg, order_id_arg, conn)
+    })
 }
 
-/// Find all orders for a user_id (from jwt).
-pub fn find_all_orders_for_user(uid: Uuid, conn: &PgConnection) -> Result<Vec<OrderDetails>, StatusCode> {
-    use crate::schema::order_items::dsl::*;
-    use crate::schema::orders::dsl::*;
+/// Create order unless request with same Idempotency-Key was already made by the user, in which case stored
+/// response is returned instead. Second element of returned tuple tells whether response is replayed.
+///
+/// Key row is locked while checking it. Concurrent request with same key waits for first one and then fails on
+/// primary key of idempotency_keys, which rolls back its order as well.
+pub fn create_order_idempotent(
+    order_id_arg: Uuid,
+    user_id_arg: Uuid,
+    key: &str,
+    new_order: &models::NewOrder,
+    conn: &PgConnection,
+) -> Result<(IdempotencyKey, bool), AppError> {
+    use crate::schema::idempotency_keys::dsl::*;
 
-    let vec: Vec<(Order, OrderItem)> = orders
-        .inner_join(order_items)
-        .filter(user_id.eq(uid))
-        .into_boxed()
-        .get_results(conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut dictionary: HashMap<&uuid::Uuid, OrderDetails> = HashMap::new();
-
-    vec.iter().for_each(|tup| {
-        let order = &tup.0;
-        let order_item = &tup.1;
-        match dictionary.get_mut(&order.order_id) {
-            // Insert record in hashmap for the first time.
-            None => dictionary.insert(
-                &order.order_id,
-                OrderDetails {
-                    order_id: order.order_id,
-                    user_id: order.user_id,
-                    note: order.note.clone(),
-                    order_total: i64::from(order_item.qty * order_item.price),
-                    order_at: order.created_at,
-                    items: None,
-                },
-            ),
-            // Update order_total for subsequent orders.
-            Some(od) => {
-                od.order_total = od.order_total + i64::from(order_item.qty + order_item.price);
-                None
+    if key.is_empty() || key.chars().count() > 255 {
+        return Err(AppError::Validation(vec![FieldError::new(
+            "Idempotency-Key",
+            "must be between 1 and 255 characters",
+        )]));
+    }
+
+    let hash = request_hash_of(new_order)?;
+
+    conn.transaction::<_, AppError, _>(|| {
+        let now = chrono::offset::Utc::now().naive_utc();
+
+        let existing = idempotency_keys
+            .filter(user_id.eq(user_id_arg))
+            .filter(idempotency_key.eq(key))
+            .for_update()
+            .first::<IdempotencyKey>(conn)
+            .optional()?;
+
+        if let Some(existing) = existing {
+            if existing.created_at + chrono::Duration::hours(IDEMPOTENCY_KEY_TTL_HOURS) > now {
+                if existing.request_hash != hash {
+                    return Err(AppError::Validation(vec![FieldError::new(
+                        "Idempotency-Key",
+                        "was already used with different request body",
+                    )]));
+                }
+                return Ok((existing, true));
             }
+
+            // Expired key, free it up for this request.
+            diesel::delete(idempotency_keys.filter(user_id.eq(user_id_arg)).filter(idempotency_key.eq(key)))
+                .execute(conn)?;
+        }
+
+        let order = create_order_with_items(order_id_arg, user_id_arg, new_order, conn)?;
+
+        let record = IdempotencyKey {
+            user_id: user_id_arg,
+            idempotency_key: key.to_owned(),
+            request_hash: hash.clone(),
+            order_id: order_id_arg,
+            response_status: 201,
+            response_body: serde_json::to_string(&order)
+                .map_err(|e| AppError::Internal(format!("couldn't serialize order: {}", e)))?,
+            created_at: now,
         };
-    });
 
-    let vec_of_order_details: Vec<OrderDetails> = dictionary.values().cloned().collect();
+        diesel::insert_into(idempotency_keys)
+            .values(&record)
+            .execute(conn)
+            .map_err(|e| match e {
+                diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => {
+                    AppError::Conflict("Request with same Idempotency-Key is already being processed.".to_owned())
+                }
+                e => AppError::Db(e),
+            })?;
+
+        Ok((record, false))
+    })
+}
+
+/// Hex encoded SHA-256 of request body.
+fn request_hash_of(new_order: &models::NewOrder) -> Result<String, AppError> {
+    use sha2::{Digest, Sha256};
+
+    let json = serde_json::to_string(new_order)
+        .map_err(|e| AppError::Internal(format!("couldn't serialize order request: {}", e)))?;
+
+    Ok(hex::encode(Sha256::digest(json.as_bytes())))
+}
+
+/// Statuses order can move to from given status.
+pub fn allowed_transitions(from: OrderStatus) -> Vec<OrderStatus> {
+    ORDER_STATUS_TRANSITIONS
+        .iter()
+        .filter(|(f, _)| *f == from)
+        .map(|(_, t)| *t)
+        .collect()
+}
+
+/// Move any user's order to new status on behalf of admin `admin_id`. Order row is locked so that concurrent updates
+/// are applied one after other and each is checked against status left by previous one.
+pub fn update_order_status(
+    admin_id: Uuid,
+    oid: Uuid,
+    precondition: &VersionPrecondition,
+    new_status: OrderStatus,
+    conn: &PgConnection,
+) -> Result<OrderDetails, AppError> {
+    conn.transaction::<_, AppError, _>(|| {
+        let order = lock_any_order(oid, conn)?;
+        precondition.check(order.version)?;
+        let current = order.status;
+
+        let allowed = allowed_transitions(current);
+        if !allowed.contains(&new_status) {
+            let allowed_str = allowed.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ");
+            return Err(AppError::Conflict(format!(
+                "Order can not move from '{}' to '{}'. Allowed next statuses: [{}].",
+                current.as_str(),
+                new_status.as_str(),
+                allowed_str
+            )));
+        }
+
+        apply_status_change(admin_id, oid, current, new_status, None, conn)?;
+
+        find_order_by_id(order.user_id, oid, conn)
+    })
+}
+
+/// Cancel user's order on customer's request. Only allowed before order is shipped.
+pub fn cancel_order(
+    user_id_arg: Uuid,
+    oid: Uuid,
+    precondition: &VersionPrecondition,
+    reason: &str,
+    conn: &PgConnection,
+) -> Result<OrderDetails, AppError> {
+    conn.transaction::<_, AppError, _>(|| {
+        let order = lock_order(user_id_arg, oid, conn)?;
+        precondition.check(order.version)?;
+        let current = order.status;
+
+        if current == OrderStatus::Cancelled {
+            return Err(AppError::Conflict("Order is already cancelled.".to_owned()));
+        }
+
+        if !CUSTOMER_CANCELLABLE_STATUSES.contains(&current) {
+            return Err(AppError::Conflict(format!(
+                "Order can not be cancelled once it is '{}'. Orders can only be cancelled before shipment.",
+                current.as_str()
+            )));
+        }
+
+        apply_status_change(user_id_arg, oid, current, OrderStatus::Cancelled, Some(reason), conn)?;
+
+        find_order_by_id(user_id_arg, oid, conn)
+    })
+}
+
+/// Edit note and items of user's order. Rejected with 412 when order has changed since version client has seen
+/// and with 409 when it is already fulfilled.
+pub fn update_order(
+    user_id_arg: Uuid,
+    oid: Uuid,
+    precondition: &VersionPrecondition,
+    update: &models::OrderUpdate,
+    conn: &PgConnection,
+) -> Result<OrderDetails, AppError> {
+    use crate::schema::order_items::dsl as oi;
+    use crate::schema::orders::dsl as o;
+
+    conn.transaction::<_, AppError, _>(|| {
+        let order = lock_order(user_id_arg, oid, conn)?;
+
+        precondition.check(order.version)?;
+
+        if !EDITABLE_STATUSES.contains(&order.status) {
+            return Err(AppError::Conflict(format!(
+                "Order can not be edited once it is '{}'. Orders can only be edited before fulfillment.",
+                order.status.as_str()
+            )));
+        }
+
+        let existing_items: Vec<(Uuid, Option<Uuid>, i32, i64)> = oi::order_items
+            .select((oi::item_id, oi::product_id, oi::qty, oi::price_minor))
+            .filter(oi::order_id.eq(oid))
+            .load(conn)?;
+        let existing_item_ids: Vec<Uuid> = existing_items.iter().map(|(id, _, _, _)| *id).collect();
+
+        // Each item may be listed only once, otherwise stock changes would not match quantities which end up stored.
+        let mut errors: Vec<FieldError> = vec![];
+        for (idx, id) in update.remove_items.iter().enumerate() {
+            if !existing_item_ids.contains(id) {
+                errors.push(FieldError::new(&format!("remove_items[{}]", idx), "is not an item of this order"));
+            } else if update.remove_items[..idx].contains(id) {
+                errors.push(FieldError::new(&format!("remove_items[{}]", idx), "is listed more than once"));
+            }
+        }
+        for (idx, item) in update.update_items.iter().enumerate() {
+            if !existing_item_ids.contains(&item.item_id) {
+                errors.push(FieldError::new(&format!("update_items[{}].item_id", idx), "is not an item of this order"));
+            } else if update.remove_items.contains(&item.item_id) {
+                errors.push(FieldError::new(&format!("update_items[{}].item_id", idx), "is also being removed"));
+            } else if update.update_items[..idx].iter().any(|prev| prev.item_id == item.item_id) {
+                errors.push(FieldError::new(&format!("update_items[{}].item_id", idx), "is listed more than once"));
+            }
+        }
+
+        let added_items = price_new_items(&update.add_items, "add_items", Some(order.currency), &mut errors, conn)?;
+
+        let removed = existing_item_ids.iter().filter(|id| update.remove_items.contains(id)).count();
+        let item_count = existing_item_ids.len() - removed + update.add_items.len();
+        if item_count == 0 || item_count > MAX_ITEMS_PER_ORDER {
+            errors.push(FieldError::new("items", "order must contain between 1 and 100 items"));
+        }
+
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+
+        let kept_lines = existing_items
+            .iter()
+            .filter(|(id, _, _, _)| !update.remove_items.contains(id))
+            .map(|(id, _, old_qty, price_minor)| {
+                let new_qty = update.update_items.iter().find(|item| item.item_id == *id).map(|item| item.qty);
+                (Money::new(*price_minor, order.currency), new_qty.unwrap_or(*old_qty))
+            });
+        let added_lines = added_items.iter().map(|item| (item.price, item.qty));
+        checked_subtotal(kept_lines.chain(added_lines)).ok_or_else(order_too_large)?;
+
+        // Removed items give their stock back, added items take it and changed quantities take or give difference.
+        let mut stock_changes: Vec<StockChange> = vec![];
+        for (id, pid, old_qty, _) in &existing_items {
+            let pid = match pid {
+                Some(pid) => *pid,
+                // Items from before product catalog have no stock.
+                None => continue,
+            };
+            if update.remove_items.contains(id) {
+                stock_changes.push(StockChange { product_id: pid, delta: *old_qty });
+            } else if let Some(item) = update.update_items.iter().find(|item| item.item_id == *id) {
+                stock_changes.push(StockChange { product_id: pid, delta: *old_qty - item.qty });
+            }
+        }
+        stock_changes.extend(added_items.iter().map(|item| StockChange {
+            product_id: item.product_id,
+            delta: -item.qty,
+        }));
+        inventory::apply_stock_changes(&stock_changes, Some(oid), user_id_arg, None, conn)?;
+
+        if !update.remove_items.is_empty() {
+            diesel::delete(oi::order_items.filter(oi::order_id.eq(oid)).filter(oi::item_id.eq_any(&update.remove_items)))
+                .execute(conn)?;
+        }
+
+        for item in &update.update_items {
+            diesel::update(oi::order_items.filter(oi::item_id.eq(item.item_id)))
+                .set(oi::qty.eq(item.qty))
+                .execute(conn)?;
+        }
+
+        if !update.add_items.is_empty() {
+            insert_new_order_items(oid, &added_items, conn)?;
+        }
+
+        // Discounts follow new subtotal.
+        if !update.add_items.is_empty() || !update.update_items.is_empty() || !update.remove_items.is_empty() {
+            let subtotal = o::orders
+                .select(order_subtotal_sql())
+                .filter(o::order_id.eq(oid))
+                .first::<i64>(conn)?;
+            discounts::reprice_order_discounts(oid, Money::new(subtotal, order.currency), conn)?;
+        }
+
+        if let Some(new_note) = &update.note {
+            diesel::update(o::orders.filter(o::order_id.eq(oid)))
+                .set(o::note.eq(new_note))
+                .execute(conn)?;
+        }
+
+        diesel::update(o::orders.filter(o::order_id.eq(oid)))
+            .set(o::version.eq(o::version + 1))
+            .execute(conn)?;
+
+        find_order_by_id(user_id_arg, oid, conn)
+    })
+}
+
+/// Give back stock taken by all items of order.
+fn release_order_stock(user_id_arg: Uuid, oid: Uuid, conn: &PgConnection) -> Result<(), AppError> {
+    use crate::schema::order_items::dsl::*;
+
+    let releases: Vec<StockChange> = order_items
+        .select((product_id, qty))
+        .filter(order_id.eq(oid))
+        .load::<(Option<Uuid>, i32)>(conn)?
+        .into_iter()
+        .filter_map(|(pid, item_qty)| pid.map(|pid| StockChange { product_id: pid, delta: item_qty }))
+        .collect();
+
+    inventory::apply_stock_changes(&releases, Some(oid), user_id_arg, None, conn)
+}
+
+/// Lock user's order row for update and return it. Should be called inside transaction.
+fn lock_order(user_id_arg: Uuid, oid: Uuid, conn: &PgConnection) -> Result<Order, AppError> {
+    use crate::schema::orders::dsl::*;
+
+    orders
+        // Only allow to access user’s own order not others
+        .filter(user_id.eq(user_id_arg))
+        .filter(order_id.eq(oid))
+        .for_update()
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Order id not correct(or not present) for the user in access_token.".to_owned()))
+}
+
+/// Lock order row of any user for update and return it. Should be called inside transaction.
+fn lock_any_order(oid: Uuid, conn: &PgConnection) -> Result<Order, AppError> {
+    use crate::schema::orders::dsl::*;
+
+    orders
+        .filter(order_id.eq(oid))
+        .for_update()
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Order is not found.".to_owned()))
+}
+
+/// Move order to new status on behalf of `user_id_arg` and record it in status history. Moving to cancelled also
+/// records cancellation. Stock taken by order is given back when it is cancelled, or refunded before it is shipped.
+fn apply_status_change(
+    user_id_arg: Uuid,
+    oid: Uuid,
+    from: OrderStatus,
+    to: OrderStatus,
+    reason: Option<&str>,
+    conn: &PgConnection,
+) -> Result<(), AppError> {
+    use crate::schema::orders::dsl::*;
+
+    let now = chrono::offset::Utc::now().naive_utc();
+
+    let before_shipment = CUSTOMER_CANCELLABLE_STATUSES.contains(&from);
+    if to == OrderStatus::Cancelled || (to == OrderStatus::Refunded && before_shipment) {
+        release_order_stock(user_id_arg, oid, conn)?;
+    }
+
+    if to == OrderStatus::Cancelled {
+        diesel::update(orders.filter(order_id.eq(oid)))
+            .set((status.eq(to), cancelled_at.eq(now), cancellation_reason.eq(reason), version.eq(version + 1)))
+            .execute(conn)?;
+    } else {
+        diesel::update(orders.filter(order_id.eq(oid)))
+            .set((status.eq(to), version.eq(version + 1)))
+            .execute(conn)?;
+    }
+
+    record_status_change(oid, Some(from), to, now, conn)
+}
+
+fn cancellation_of(order: &Order) -> Option<OrderCancellation> {
+    order.cancelled_at.map(|at| OrderCancellation {
+        reason: order.cancellation_reason.clone(),
+        cancelled_at: at,
+    })
+}
 
-    Ok(vec_of_order_details)
+fn record_status_change(
+    oid: Uuid,
+    from: Option<OrderStatus>,
+    to: OrderStatus,
+    at: chrono::NaiveDateTime,
+    conn: &PgConnection,
+) -> Result<(), AppError> {
+    use crate::schema::order_status_changes::dsl::*;
+
+    let change = OrderStatusChange {
+        change_id: Uuid::new_v4(),
+        order_id: oid,
+        from_status: from,
+        to_status: to,
+        changed_at: at,
+    };
+
+    diesel::insert_into(order_status_changes)
+        .values(&change)
+        .execute(conn)?;
+
+    Ok(())
 }
 
+/// Status transitions of order, oldest first.
+fn find_status_history(oid: Uuid, conn: &PgConnection) -> Result<Vec<OrderStatusChangeDetails>, AppError> {
+    use crate::schema::order_status_changes::dsl::*;
+
+    let changes = order_status_changes
+        .filter(order_id.eq(oid))
+        .order(changed_at.asc())
+        .load::<OrderStatusChange>(conn)?;
+
+    Ok(changes
+        .into_iter()
+        .map(|c| OrderStatusChangeDetails {
+            from_status: c.from_status,
+            to_status: c.to_status,
+            changed_at: c.changed_at,
+        })
+        .collect())
+}
 
 pub fn insert_new_order(
     order_id_arg: uuid::Uuid,
     user_id_arg: uuid::Uuid,
     note_arg: Option<String>,
+    currency_arg: Currency,
     conn: &PgConnection,
-) -> Result<models::Order, StatusCode> {
+) -> Result<models::Order, AppError> {
     // It is common when using Diesel with Actix web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
@@ -127,21 +894,27 @@ pub fn insert_new_order(
         user_id: user_id_arg,
         note: note_arg,
         created_at: chrono::offset::Utc::now().naive_utc(),
+        status: OrderStatus::Pending,
+        cancellation_reason: None,
+        cancelled_at: None,
+        version: 1,
+        currency: currency_arg,
     };
 
     diesel::insert_into(orders)
         .values(&new_order)
-        .execute(conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .execute(conn)?;
+
+    record_status_change(order_id_arg, None, OrderStatus::Pending, new_order.created_at, conn)?;
 
     Ok(new_order)
 }
 
 pub fn insert_new_order_items(
     order_id_arg: uuid::Uuid,
-    order_items_arg: &Vec<NewOrderItem>,
+    order_items_arg: &[PricedOrderItem],
     conn: &PgConnection,
-) -> Result<bool, StatusCode> {
+) -> Result<bool, AppError> {
     // It is common when using Diesel with Actix web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
@@ -154,15 +927,185 @@ pub fn insert_new_order_items(
             order_id: order_id_arg,
             description: oi.description.clone(),
             qty: oi.qty,
-            price: oi.price,
             created_at: chrono::offset::Utc::now().naive_utc(),
+            price_minor: oi.price.amount_minor,
+            currency: oi.price.currency,
+            product_id: Some(oi.product_id),
         })
         .collect::<Vec<_>>();
 
     diesel::insert_into(order_items)
         .values(&new_order_items)
-        .execute(conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .execute(conn)?;
 
     Ok(true)
 }
+
+// These tests need db with migrations applied, at TEST_DATABASE_URL. They do nothing when it is not set or db can
+// not be reached, `.env` is not read so that plain `cargo test` does not depend on local db. Everything is done in
+// test transaction, which is rolled back at the end.
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use diesel::prelude::*;
+    use uuid::Uuid;
+
+    use super::models::{OrderListQuery, OrderSort};
+    use super::{find_all_orders_for_user, find_order_by_id};
+    use crate::errors::AppError;
+
+    fn connection() -> Option<PgConnection> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        PgConnection::establish(&url).ok()
+    }
+
+    fn insert_user(conn: &PgConnection) -> Uuid {
+        use crate::schema::users::dsl::*;
+
+        let uid = Uuid::new_v4();
+        diesel::insert_into(users)
+            .values((
+                user_id.eq(uid),
+                first_name.eq("Test"),
+                last_name.eq("User"),
+                email.eq(format!("{}@example.com", uid)),
+                password.eq("not a hash"),
+                created_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)
+            .unwrap();
+        uid
+    }
+
+    /// Insert order with items given as `(qty, price_minor)` and discount lines given as their amounts.
+    fn insert_order(uid: Uuid, at: &str, items: &[(i32, i64)], discounts: &[i64], conn: &PgConnection) -> Uuid {
+        use crate::schema::{coupons, order_discounts, order_items, orders};
+
+        let oid = Uuid::new_v4();
+        let at = NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").unwrap();
+
+        diesel::insert_into(orders::table)
+            .values((
+                orders::order_id.eq(oid),
+                orders::user_id.eq(uid),
+                orders::created_at.eq(at),
+                orders::currency.eq("USD"),
+            ))
+            .execute(conn)
+            .unwrap();
+
+        for (item_qty, item_price_minor) in items {
+            diesel::insert_into(order_items::table)
+                .values((
+                    order_items::item_id.eq(Uuid::new_v4()),
+                    order_items::order_id.eq(oid),
+                    order_items::description.eq("Item"),
+                    order_items::qty.eq(item_qty),
+                    order_items::created_at.eq(at),
+                    order_items::price_minor.eq(item_price_minor),
+                    order_items::currency.eq("USD"),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        for discount_minor in discounts {
+            let cid = Uuid::new_v4();
+            diesel::insert_into(coupons::table)
+                .values((
+                    coupons::coupon_id.eq(cid),
+                    coupons::code.eq(cid.to_string()),
+                    coupons::kind.eq("fixed"),
+                    coupons::amount_off_minor.eq(discount_minor),
+                    coupons::currency.eq("USD"),
+                    coupons::created_at.eq(at),
+                ))
+                .execute(conn)
+                .unwrap();
+            diesel::insert_into(order_discounts::table)
+                .values((
+                    order_discounts::discount_id.eq(Uuid::new_v4()),
+                    order_discounts::order_id.eq(oid),
+                    order_discounts::coupon_id.eq(cid),
+                    order_discounts::user_id.eq(uid),
+                    order_discounts::code.eq("TEST"),
+                    order_discounts::amount_minor.eq(discount_minor),
+                    order_discounts::created_at.eq(at),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+
+        oid
+    }
+
+    fn list_query() -> OrderListQuery {
+        OrderListQuery {
+            cursor: None,
+            limit: Some(100),
+            sort: OrderSort::default(),
+            from: None,
+            to: None,
+            include: None,
+            fields: None,
+        }
+    }
+
+    #[test]
+    fn detail_total_is_subtotal_less_discounts() {
+        let conn = match connection() {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        conn.test_transaction::<_, AppError, _>(|| {
+            let uid = insert_user(&conn);
+            let oid = insert_order(uid, "2026-01-01 10:00:00", &[(2, 250), (1, 1000)], &[300, 50], &conn);
+
+            let order = find_order_by_id(uid, oid, &conn)?;
+            assert_eq!(order.subtotal.amount_minor, 1500);
+            assert_eq!(order.order_total.amount_minor, 1150);
+
+            let empty = insert_order(uid, "2026-01-02 10:00:00", &[], &[], &conn);
+            let order = find_order_by_id(uid, empty, &conn)?;
+            assert_eq!(order.subtotal.amount_minor, 0);
+            assert_eq!(order.order_total.amount_minor, 0);
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn list_and_detail_totals_agree() {
+        let conn = match connection() {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        conn.test_transaction::<_, AppError, _>(|| {
+            let uid = insert_user(&conn);
+            insert_order(uid, "2026-01-01 10:00:00", &[(3, 199)], &[], &conn);
+            insert_order(uid, "2026-01-02 10:00:00", &[(2, 250), (1, 1000)], &[300], &conn);
+            insert_order(uid, "2026-01-03 10:00:00", &[(1, 500)], &[100, 25], &conn);
+            insert_order(uid, "2026-01-04 10:00:00", &[], &[], &conn);
+
+            for sort in &[OrderSort::CreatedAt, OrderSort::Total, OrderSort::TotalDesc] {
+                let query = OrderListQuery { sort: *sort, ..list_query() };
+                let page = find_all_orders_for_user(uid, &query, &conn)?;
+                assert_eq!(page.orders.len(), 4);
+
+                for listed in &page.orders {
+                    let detail = find_order_by_id(uid, listed.order_id, &conn)?;
+                    assert_eq!(listed.subtotal, detail.subtotal);
+                    assert_eq!(listed.order_total, detail.order_total);
+                }
+            }
+
+            let page = find_all_orders_for_user(uid, &list_query(), &conn)?;
+            let totals: Vec<i64> = page.orders.iter().map(|order| order.order_total.amount_minor).collect();
+            assert_eq!(totals, vec![0, 375, 1200, 597]);
+
+            Ok(())
+        });
+    }
+}
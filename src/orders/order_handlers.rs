@@ -1,119 +1,204 @@
 //! Diesel does not support async operations, i.e. diesel operations are blocking, so we have to run it in separate threads using the web::block
 //! function which offloads blocking code (like Diesel's) in order to not block the server's thread.
 
-use actix_web::error::{BlockingError, ErrorInternalServerError, ErrorNotFound, ErrorUnauthorized};
-use actix_web::http::StatusCode;
-use actix_web::{get, post, web, Error, HttpRequest, HttpResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::{get, patch, post, web, HttpRequest, HttpResponse};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use uuid::Uuid;
+use validator::Validate;
+
+use crate::errors::{AppError, FieldError};
+use crate::preconditions::{self, version_etag, VersionPrecondition};
+use crate::users::auth::AuthenticatedUser;
 
 #[path = "./order_models.rs"] mod models;
 #[path = "./order_actions.rs"] mod actions;
-#[path = "../users/user_actions.rs"] mod user_actions;
 
 type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
-/// Inserts new user with name defined in body.
+/// Creates new order for authenticated user. Responds with 201 and details of created order.
+/// When Idempotency-Key header is given, retries with same key and body get the original response back instead of
+/// creating duplicate order.
 #[post("/api/v1/orders")]
 pub async fn create_order(
     req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<DbPool>,
     body: web::Json<actions::models::NewOrder>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool
-        .get()
-        .map_err(|_| ErrorInternalServerError("couldn't get db connection from pool. Please retry."))?;
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
 
     let order_id = Uuid::new_v4();
-    let note_option = body.note.clone();
 
-    let jwt_header = req.headers().get("access_token").cloned();
+    let key = match req.headers().get("Idempotency-Key") {
+        Some(v) => v.to_str().map(str::to_owned).map_err(|_| {
+            AppError::Validation(vec![FieldError::new("Idempotency-Key", "must be visible ASCII characters")])
+        })?,
+        None => {
+            // use web::block to offload blocking Diesel code without blocking server thread
+            let order = web::block(move || actions::create_order_with_items(order_id, user.user_id, &body, &conn)).await?;
+
+            return Ok(HttpResponse::Created()
+                .header(header::LOCATION, format!("/api/v1/orders/{}", order_id))
+                .json(order));
+        }
+    };
 
     // use web::block to offload blocking Diesel code without blocking server thread
-    web::block(move || {
-        // Todo: Convert authenticate_request function to actix middleware.
-        let user_id = user_actions::authenticate_request(jwt_header, &conn)?;
-        let order = actions::insert_new_order(order_id.clone(), user_id, note_option, &conn);
-        actions::insert_new_order_items(order_id, &body.items, &conn)?;
-        order
-    })
-    .await
-    .map_err(|e| {
-        match e {
-            BlockingError::Error(StatusCode::UNAUTHORIZED) => {
-                ErrorUnauthorized("Provide proper access token")
-            }
-            BlockingError::Error(StatusCode::NOT_FOUND) => {
-                ErrorNotFound("User in access_token is not found in db to create new order.")
-            }
-            _ => ErrorInternalServerError("Something unexpected happened. Please retry"),
-        }
-    })?;
+    let (record, replayed) =
+        web::block(move || actions::create_order_idempotent(order_id, user.user_id, &key, &body, &conn)).await?;
+
+    let status = StatusCode::from_u16(record.response_status as u16)
+        .map_err(|_| AppError::Internal(format!("invalid stored response status {}", record.response_status)))?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(HttpResponse::build(status)
+        .header(header::LOCATION, format!("/api/v1/orders/{}", record.order_id))
+        .header("Idempotent-Replayed", if replayed { "true" } else { "false" })
+        .content_type("application/json")
+        .body(record.response_body))
 }
 
+/// Lists orders of user one page at a time, see `OrderListQuery` for paging, sorting, filtering, embedding items
+/// and sparse fieldsets.
+/// Response carries ETag derived from its content, so that clients can revalidate with If-None-Match.
 #[get("/api/v1/orders")]
 pub async fn get_order_details_for_user(
     req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<DbPool>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().map_err(|_| ErrorInternalServerError("couldn't get db connection from pool. Please retry."))?;
+    query: web::Query<actions::models::OrderListQuery>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid query before any db work.
+    query.validate()?;
+    let fields = query.field_set();
 
-    let jwt_header = req.headers().get("access_token").cloned();
+    let conn = pool.get()?;
 
     // use web::block to offload blocking Diesel code without blocking server thread
-    let order_details = web::block(move || {
-        let user_id = user_actions::authenticate_request(jwt_header, &conn)?;
+    let order_page =
+        web::block(move || actions::find_all_orders_for_user(user.user_id, &query, &conn)).await?;
 
-        actions::find_all_orders_for_user(user_id, &conn)
-    })
-    .await
-    .map_err(|e| {
-        println!(" Print errors{}", e);
-        match e {
-        BlockingError::Error(StatusCode::UNAUTHORIZED) => {
-            ErrorUnauthorized("Provide proper access token")
-        }
-        BlockingError::Error(StatusCode::NOT_FOUND) => {
-            ErrorNotFound("Incorrect access_token provided. Provide right access_token.")
+    let body = select_fields(&order_page, fields.as_deref())?;
+    let etag = preconditions::content_etag(&body)?;
+
+    Ok(preconditions::json_with_etag(&req, etag, &body))
+}
+
+/// Keep only requested fields of each order in page (sparse fieldset). `order_id` is always kept.
+fn select_fields(
+    page: &actions::models::OrderPage,
+    fields: Option<&[String]>,
+) -> Result<serde_json::Value, AppError> {
+    let mut value = serde_json::to_value(page)
+        .map_err(|e| AppError::Internal(format!("couldn't serialize order page: {}", e)))?;
+
+    if let (Some(fields), Some(orders)) = (fields, value.get_mut("orders").and_then(|o| o.as_array_mut())) {
+        for order in orders.iter_mut().filter_map(|o| o.as_object_mut()) {
+            order.retain(|k, _| k == "order_id" || fields.iter().any(|f| f == k));
         }
-        _ => ErrorInternalServerError("Something unexpected happened. Please retry"),
-    }})?;
+    }
 
-    Ok(HttpResponse::Ok().json(order_details))
+    Ok(value)
 }
 
-/// Finds user by UID.
+/// Finds order by UID. Response carries ETag of current version of order.
 #[get("/api/v1/orders/{order_id}")]
 pub async fn get_order_by_id(
     req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    order_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    let order_id = order_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let order = web::block(move || actions::find_order_by_id(user.user_id, order_id, &conn)).await?;
+
+    Ok(preconditions::json_with_etag(&req, version_etag(order.version), &order))
+}
+
+/// Edits note and items of order before it is fulfilled. Request must carry `If-Match` with ETag of order client
+/// has seen, concurrent edits are rejected with 412.
+#[patch("/api/v1/orders/{order_id}")]
+pub async fn update_order(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    order_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::OrderUpdate>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+    let precondition = VersionPrecondition::from_request(&req)?;
+
+    let conn = pool.get()?;
+
+    let order_id = order_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let order =
+        web::block(move || actions::update_order(user.user_id, order_id, &precondition, &body, &conn)).await?;
+
+    Ok(HttpResponse::Ok().header(header::ETAG, version_etag(order.version)).json(order))
+}
+
+/// Moves order to new status. Transitions not allowed from current status are rejected with 409. Admin only,
+/// customers cancel their orders through `/cancel`.
+/// Request must carry `If-Match` with ETag of order.
+#[patch("/api/v1/orders/{order_id}/status")]
+pub async fn update_order_status(
+    req: HttpRequest,
+    user: AuthenticatedUser,
     pool: web::Data<DbPool>,
     order_uid: web::Path<Uuid>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().map_err(|_| ErrorInternalServerError("couldn't get db connection from pool. Please retry."))?;
+    body: web::Json<actions::models::OrderStatusUpdate>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    let precondition = VersionPrecondition::from_request(&req)?;
+
+    let conn = pool.get()?;
 
     let order_id = order_uid.into_inner();
-    let jwt_header = req.headers().get("access_token").cloned();
 
     // use web::block to offload blocking Diesel code without blocking server thread
     let order = web::block(move || {
-        // Todo: Convert authenticate_request function to actix middleware.
-        let user_id = user_actions::authenticate_request(jwt_header, &conn)?;
+        actions::update_order_status(user.user_id, order_id, &precondition, body.status, &conn)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().header(header::ETAG, version_etag(order.version)).json(order))
+}
+
+/// Cancels order on customer's request. Orders which are already shipped can not be cancelled.
+/// Request must carry `If-Match` with ETag of order.
+#[post("/api/v1/orders/{order_id}/cancel")]
+pub async fn cancel_order(
+    req: HttpRequest,
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    order_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::CancelOrder>,
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+    let precondition = VersionPrecondition::from_request(&req)?;
+
+    let conn = pool.get()?;
 
-        actions::find_order_by_id(user_id, order_id, &conn)
+    let order_id = order_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let order = web::block(move || {
+        actions::cancel_order(user.user_id, order_id, &precondition, &body.reason, &conn)
     })
-    .await
-    .map_err(|e| match e {
-        BlockingError::Error(StatusCode::UNAUTHORIZED) => {
-            ErrorUnauthorized("Provide proper access token")
-        }
-        BlockingError::Error(StatusCode::NOT_FOUND) => {
-            ErrorNotFound("Order id not correct(or not present) for the user in access_token.")
-        }
-        _ => ErrorInternalServerError("Something unexpected happened. Please retry"),
-    })?;
+    .await?;
 
-    Ok(HttpResponse::Ok().json(order))
+    Ok(HttpResponse::Ok().header(header::ETAG, version_etag(order.version)).json(order))
 }
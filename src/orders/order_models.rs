@@ -1,46 +1,149 @@
+use diesel::deserialize::{self, FromSql};
+use diesel::pg::Pg;
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use validator::{Validate, ValidationError};
 
+use crate::money::{Currency, Money};
+use crate::schema::idempotency_keys;
+use crate::schema::order_addresses;
 use crate::schema::orders;
 use crate::schema::order_items;
+use crate::schema::order_status_changes;
+
+/// Lifecycle status of order. Stored as lowercase string in `orders.status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Varchar"]
+pub enum OrderStatus {
+    Pending,
+    Paid,
+    Fulfilled,
+    Shipped,
+    Delivered,
+    Cancelled,
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Fulfilled => "fulfilled",
+            OrderStatus::Shipped => "shipped",
+            OrderStatus::Delivered => "delivered",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+}
+
+impl ToSql<Varchar, Pg> for OrderStatus {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Varchar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Pg> for OrderStatus {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <String as FromSql<Varchar, Pg>>::from_sql(bytes)?.as_str() {
+            "pending" => Ok(OrderStatus::Pending),
+            "paid" => Ok(OrderStatus::Paid),
+            "fulfilled" => Ok(OrderStatus::Fulfilled),
+            "shipped" => Ok(OrderStatus::Shipped),
+            "delivered" => Ok(OrderStatus::Delivered),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "refunded" => Ok(OrderStatus::Refunded),
+            other => Err(format!("unknown order status '{}'", other).into()),
+        }
+    }
+}
 
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, Identifiable)]
+#[primary_key(order_id)]
 pub struct Order {
     pub order_id: uuid::Uuid,
     pub user_id: uuid::Uuid,
     pub note: Option<String>,
-    pub created_at: chrono::NaiveDateTime
+    pub created_at: chrono::NaiveDateTime,
+    pub status: OrderStatus,
+    pub cancellation_reason: Option<String>,
+    pub cancelled_at: Option<chrono::NaiveDateTime>,
+    pub version: i32,
+    // All items of order are in this currency.
+    pub currency: Currency
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable, Identifiable, Associations)]
+#[primary_key(item_id)]
+#[belongs_to(Order, foreign_key = "order_id")]
 pub struct OrderItem {
     pub item_id: uuid::Uuid,
     pub order_id: uuid::Uuid,
     pub description: String,
     pub qty: i32,
-    pub price: i32,
-    pub created_at: chrono::NaiveDateTime
+    pub created_at: chrono::NaiveDateTime,
+    pub price_minor: i64,
+    pub currency: Currency,
+    // Product which was ordered. Missing for items created before product catalog existed.
+    pub product_id: Option<uuid::Uuid>
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl OrderItem {
+    pub fn price(&self) -> Money {
+        Money::new(self.price_minor, self.currency)
+    }
+}
+
+/// Item of new order. Name and price are taken from product at the time of ordering, not from client.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct NewOrderItem {
+    pub product_id: uuid::Uuid,
+    #[validate(range(min = 1, max = 10000, message = "must be between 1 and 10000"))]
+    pub qty: i32,
+}
+
+/// New order item with snapshot of its product, ready to be inserted.
+#[derive(Debug, Clone)]
+pub struct PricedOrderItem {
+    pub product_id: uuid::Uuid,
     pub description: String,
     pub qty: i32,
-    pub price: i32
+    pub price: Money,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Length limits match varchar columns of orders table.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct NewOrder {
+    #[validate(length(max = 500, message = "must be at most 500 characters"))]
     pub note: Option<String>,
-    pub items: Vec<NewOrderItem>
+    // At most 100 items per order.
+    #[validate(length(min = 1, max = 100, message = "must contain between 1 and 100 items"))]
+    #[validate]
+    pub items: Vec<NewOrderItem>,
+    // Addresses from address book of user, copied to order. Billing address defaults to shipping address.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shipping_address_id: Option<uuid::Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub billing_address_id: Option<uuid::Uuid>,
+    // Code of coupon to apply, matched case insensitively. At most one coupon can be applied to order.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[validate(length(min = 1, max = 64, message = "must be between 1 and 64 characters"))]
+    pub coupon_code: Option<String>
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderItemDetails {
     pub item_id: uuid::Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<uuid::Uuid>,
     pub description: String,
     pub qty: i32,
-    pub price: i32,
+    pub price: Money,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,9 +151,309 @@ pub struct OrderDetails {
     pub order_id: uuid::Uuid,
     pub user_id: uuid::Uuid,
     pub note: Option<String>,
-    pub order_total: i64,
+    // Sum of items, before discounts.
+    pub subtotal: Money,
+    pub order_total: Money,
     pub order_at: chrono::NaiveDateTime,
+    pub status: OrderStatus,
+    pub version: i32,
+    pub cancelled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancellation: Option<OrderCancellation>,
     // Items will be skipped when serialized if it is null.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub items: Option<Vec<OrderItemDetails>>
+    pub items: Option<Vec<OrderItemDetails>>,
+    // Status history is only present in order detail view.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_history: Option<Vec<OrderStatusChangeDetails>>,
+    // Addresses are only present in order detail view, and only when order was placed with them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shipping_address: Option<OrderAddressDetails>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub billing_address: Option<OrderAddressDetails>,
+    // Discount lines are only present in order detail view.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discounts: Option<Vec<OrderDiscountDetails>>
 }
+
+/// Discount line of order. `order_total` is `subtotal` less all discounts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderDiscountDetails {
+    pub coupon_code: String,
+    pub amount: Money,
+}
+
+/// Which address of order it is. Stored as lowercase string in `order_addresses.kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, AsExpression, FromSqlRow)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Varchar"]
+pub enum AddressKind {
+    Shipping,
+    Billing,
+}
+
+impl AddressKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressKind::Shipping => "shipping",
+            AddressKind::Billing => "billing",
+        }
+    }
+}
+
+impl ToSql<Varchar, Pg> for AddressKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        ToSql::<Varchar, Pg>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Pg> for AddressKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <String as FromSql<Varchar, Pg>>::from_sql(bytes)?.as_str() {
+            "shipping" => Ok(AddressKind::Shipping),
+            "billing" => Ok(AddressKind::Billing),
+            other => Err(format!("unknown address kind '{}'", other).into()),
+        }
+    }
+}
+
+/// Copy of address from address book taken when order is placed.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[table_name = "order_addresses"]
+pub struct OrderAddress {
+    pub order_id: uuid::Uuid,
+    pub kind: AddressKind,
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+    pub phone: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Queryable)]
+pub struct OrderAddressDetails {
+    pub full_name: String,
+    pub line1: String,
+    pub line2: Option<String>,
+    pub city: String,
+    pub region: Option<String>,
+    pub postal_code: String,
+    pub country: String,
+    pub phone: Option<String>,
+}
+
+impl OrderAddress {
+    pub fn new(order_id: uuid::Uuid, kind: AddressKind, address: OrderAddressDetails) -> OrderAddress {
+        OrderAddress {
+            order_id,
+            kind,
+            full_name: address.full_name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+            phone: address.phone,
+        }
+    }
+}
+
+impl From<OrderAddress> for OrderAddressDetails {
+    fn from(address: OrderAddress) -> Self {
+        OrderAddressDetails {
+            full_name: address.full_name,
+            line1: address.line1,
+            line2: address.line2,
+            city: address.city,
+            region: address.region,
+            postal_code: address.postal_code,
+            country: address.country,
+            phone: address.phone,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderCancellation {
+    pub reason: Option<String>,
+    pub cancelled_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct OrderStatusChange {
+    pub change_id: uuid::Uuid,
+    pub order_id: uuid::Uuid,
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub changed_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderStatusChangeDetails {
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    pub changed_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderStatusUpdate {
+    pub status: OrderStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct OrderItemQtyUpdate {
+    pub item_id: uuid::Uuid,
+    #[validate(range(min = 1, max = 10000, message = "must be between 1 and 10000"))]
+    pub qty: i32,
+}
+
+/// Partial update of order. Fields which are absent are left unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct OrderUpdate {
+    // Absent means unchanged, null clears the note.
+    #[serde(default, deserialize_with = "deserialize_present")]
+    #[validate(length(max = 500, message = "must be at most 500 characters"))]
+    pub note: Option<Option<String>>,
+    #[serde(default)]
+    #[validate]
+    pub add_items: Vec<NewOrderItem>,
+    #[serde(default)]
+    #[validate]
+    pub update_items: Vec<OrderItemQtyUpdate>,
+    #[serde(default)]
+    pub remove_items: Vec<uuid::Uuid>,
+}
+
+/// Deserialize field which is present in payload (possibly as null) into `Some`, so that it can be told apart
+/// from absent field which `#[serde(default)]` turns into `None`.
+fn deserialize_present<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: serde::Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CancelOrder {
+    #[validate(length(min = 1, max = 500, message = "must be between 1 and 500 characters"))]
+    pub reason: String,
+}
+
+/// Sort order of order list. Leading `-` means descending. Newest orders come first by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OrderSort {
+    #[serde(rename = "created_at")]
+    CreatedAt,
+    #[default]
+    #[serde(rename = "-created_at")]
+    CreatedAtDesc,
+    #[serde(rename = "total")]
+    Total,
+    #[serde(rename = "-total")]
+    TotalDesc,
+}
+
+impl OrderSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderSort::CreatedAt => "created_at",
+            OrderSort::CreatedAtDesc => "-created_at",
+            OrderSort::Total => "total",
+            OrderSort::TotalDesc => "-total",
+        }
+    }
+}
+
+/// Query string of order list. `from` is inclusive and `to` is exclusive, both RFC 3339 timestamps.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct OrderListQuery {
+    // Opaque value of `next_cursor` from previous page.
+    pub cursor: Option<String>,
+    #[validate(range(min = 1, max = 100, message = "must be between 1 and 100"))]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub sort: OrderSort,
+    pub from: Option<chrono::DateTime<chrono::Utc>>,
+    pub to: Option<chrono::DateTime<chrono::Utc>>,
+    // Comma separated relations to embed in each order. Only `items` is supported.
+    #[validate(custom = "validate_include")]
+    pub include: Option<String>,
+    // Comma separated fields of order to return (sparse fieldset). `order_id` is always returned.
+    #[validate(custom = "validate_fields")]
+    pub fields: Option<String>,
+}
+
+/// Fields of order which can be asked for with `fields`.
+static ORDER_LIST_FIELDS: &[&str] = &[
+    "order_id",
+    "user_id",
+    "note",
+    "subtotal",
+    "order_total",
+    "order_at",
+    "status",
+    "version",
+    "cancelled",
+    "cancellation",
+    "items",
+];
+
+impl OrderListQuery {
+    pub fn include_items(&self) -> bool {
+        self.include.as_deref().is_some_and(|v| split_list(v).any(|r| r == "items"))
+    }
+
+    /// Requested fields, `None` when all fields should be returned.
+    pub fn field_set(&self) -> Option<Vec<String>> {
+        self.fields.as_deref().map(|v| split_list(v).map(str::to_owned).collect())
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|v| !v.is_empty())
+}
+
+fn validate_include(value: &str) -> Result<(), ValidationError> {
+    if split_list(value).all(|r| r == "items") {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("include");
+        err.message = Some("only 'items' can be included".into());
+        Err(err)
+    }
+}
+
+fn validate_fields(value: &str) -> Result<(), ValidationError> {
+    if split_list(value).all(|f| ORDER_LIST_FIELDS.contains(&f)) {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("fields");
+        err.message = Some(format!("must be comma separated list of: {}", ORDER_LIST_FIELDS.join(", ")).into());
+        Err(err)
+    }
+}
+
+/// Single page of order list. `next_cursor` is absent on last page.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderPage {
+    pub orders: Vec<OrderDetails>,
+    pub next_cursor: Option<String>,
+}
+
+
+/// Stored response of order creation request made with Idempotency-Key header.
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct IdempotencyKey {
+    pub user_id: uuid::Uuid,
+    pub idempotency_key: String,
+    pub request_hash: String,
+    pub order_id: uuid::Uuid,
+    pub response_status: i32,
+    pub response_body: String,
+    pub created_at: chrono::NaiveDateTime
+}
\ No newline at end of file
@@ -0,0 +1,20 @@
+//! Helpers shared by request payloads of several modules.
+
+use validator::ValidationError;
+
+use crate::money::Money;
+
+/// Default of `active` flag, things are active unless payload says otherwise.
+pub fn default_active() -> bool {
+    true
+}
+
+pub fn validate_non_negative(amount: &Money) -> Result<(), ValidationError> {
+    if amount.amount_minor >= 0 {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("range");
+        err.message = Some("must not be negative".into());
+        Err(err)
+    }
+}
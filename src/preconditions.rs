@@ -0,0 +1,97 @@
+//! Conditional requests (RFC 7232) based on version counter of resources.
+//!
+//! Versioned resources are sent with strong `ETag` like `"v3"`. Mutating endpoints require `If-Match` carrying
+//! it (or `*`) and are rejected with 412 when resource has changed since, GETs answer `If-None-Match` with 304.
+
+use actix_web::http::header;
+use actix_web::{HttpRequest, HttpResponse};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::errors::AppError;
+
+/// Versions of resource `If-Match` header allows the request to be applied to.
+#[derive(Debug, Clone)]
+pub enum VersionPrecondition {
+    /// `If-Match: *`
+    Any,
+    OneOf(Vec<i32>),
+}
+
+impl VersionPrecondition {
+    /// Read `If-Match` header. Missing header is error as mutating requests must be conditional.
+    pub fn from_request(req: &HttpRequest) -> Result<VersionPrecondition, AppError> {
+        let value = req
+            .headers()
+            .get(header::IF_MATCH)
+            .ok_or_else(|| {
+                AppError::PreconditionRequired("If-Match header with ETag of resource is required.".to_owned())
+            })?
+            .to_str()
+            .map_err(|_| AppError::PreconditionFailed("If-Match header is not valid.".to_owned()))?;
+
+        if value.trim() == "*" {
+            return Ok(VersionPrecondition::Any);
+        }
+
+        // Weak tags never match as If-Match uses strong comparison. Tags which are not ours can't match either.
+        let versions = value
+            .split(',')
+            .map(str::trim)
+            .filter_map(|tag| tag.strip_prefix("\"v")?.strip_suffix('"')?.parse::<i32>().ok())
+            .collect();
+
+        Ok(VersionPrecondition::OneOf(versions))
+    }
+
+    pub fn matches(&self, version: i32) -> bool {
+        match self {
+            VersionPrecondition::Any => true,
+            VersionPrecondition::OneOf(versions) => versions.contains(&version),
+        }
+    }
+
+    /// Error to return when current version of resource does not match.
+    pub fn check(&self, version: i32) -> Result<(), AppError> {
+        if self.matches(version) {
+            Ok(())
+        } else {
+            Err(AppError::PreconditionFailed(format!(
+                "Resource has been modified, its current ETag is {}. Reload it and retry.",
+                version_etag(version)
+            )))
+        }
+    }
+}
+
+/// Strong ETag of given version of resource.
+pub fn version_etag(version: i32) -> String {
+    format!("\"v{}\"", version)
+}
+
+/// Strong ETag derived from content of response, for resources which have no version of their own (e.g. lists).
+pub fn content_etag<T: Serialize>(body: &T) -> Result<String, AppError> {
+    let json = serde_json::to_vec(body).map_err(|e| AppError::Internal(format!("couldn't serialize response: {}", e)))?;
+    Ok(format!("\"{}\"", &hex::encode(Sha256::digest(&json))[..32]))
+}
+
+/// Json response carrying ETag, or 304 Not Modified when `If-None-Match` of request already has it.
+pub fn json_with_etag<T: Serialize>(req: &HttpRequest, etag: String, body: &T) -> HttpResponse {
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| {
+            // If-None-Match uses weak comparison, so W/ prefix is ignored.
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == etag)
+        })
+        .unwrap_or(false);
+
+    if not_modified {
+        HttpResponse::NotModified().header(header::ETAG, etag).finish()
+    } else {
+        HttpResponse::Ok().header(header::ETAG, etag).json(body)
+    }
+}
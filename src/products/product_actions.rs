@@ -0,0 +1,141 @@
+#[path = "./product_models.rs"] pub mod models;
+
+use diesel::prelude::*;
+use models::{Product, ProductDetails};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+use crate::inventory::{self, StockAdjustment, StockChange};
+
+/// Find product by product_id. Inactive products are only visible when `include_inactive` is set (i.e. to admins).
+pub fn find_product_by_id(
+    pid: Uuid,
+    include_inactive: bool,
+    conn: &PgConnection,
+) -> Result<ProductDetails, AppError> {
+    // It is common when using Diesel with Actix web to import schema-related
+    // modules inside a function's scope (rather than the normal module's scope)
+    // to prevent import collisions and namespace pollution.
+    use crate::schema::products::dsl::*;
+
+    let product = products
+        .filter(product_id.eq(pid))
+        .first::<Product>(conn)
+        .optional()?
+        .filter(|p| p.active || include_inactive)
+        .ok_or_else(|| AppError::NotFound("Product is not found.".to_owned()))?;
+
+    Ok(product.into())
+}
+
+/// List products ordered by name. Inactive products are only listed when `include_inactive` is set.
+pub fn find_all_products(include_inactive: bool, conn: &PgConnection) -> Result<Vec<ProductDetails>, AppError> {
+    use crate::schema::products::dsl::*;
+
+    let mut query = products.into_boxed();
+    if !include_inactive {
+        query = query.filter(active.eq(true));
+    }
+
+    let list = query.order((name.asc(), sku.asc())).load::<Product>(conn)?;
+
+    Ok(list.into_iter().map(ProductDetails::from).collect())
+}
+
+/// Insert new product. SKU which is already taken is rejected with 409.
+pub fn insert_new_product(new_product: &models::NewProduct, conn: &PgConnection) -> Result<ProductDetails, AppError> {
+    use crate::schema::products::dsl::*;
+
+    let now = chrono::offset::Utc::now().naive_utc();
+
+    let product = Product {
+        product_id: Uuid::new_v4(),
+        sku: new_product.sku.clone(),
+        name: new_product.name.clone(),
+        price_minor: new_product.price.amount_minor,
+        currency: new_product.price.currency,
+        active: new_product.active,
+        created_at: now,
+        updated_at: now,
+        // Stock is added afterwards with stock adjustment, so that it is in audit trail.
+        stock_available: 0,
+    };
+
+    diesel::insert_into(products)
+        .values(&product)
+        .execute(conn)
+        .map_err(|e| match e {
+            diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) => {
+                AppError::Conflict(format!("Product with SKU '{}' already exists.", new_product.sku))
+            }
+            e => AppError::Db(e),
+        })?;
+
+    Ok(product.into())
+}
+
+/// Change name, price or active flag of product. Orders placed earlier keep price they were placed with.
+pub fn update_product(
+    pid: Uuid,
+    update: &models::ProductUpdate,
+    conn: &PgConnection,
+) -> Result<ProductDetails, AppError> {
+    use crate::schema::products::dsl::*;
+
+    conn.transaction::<_, AppError, _>(|| {
+        let product = products
+            .filter(product_id.eq(pid))
+            .for_update()
+            .first::<Product>(conn)
+            .optional()?
+            .ok_or_else(|| AppError::NotFound("Product is not found.".to_owned()))?;
+
+        let updated = Product {
+            name: update.name.clone().unwrap_or(product.name),
+            price_minor: update.price.map_or(product.price_minor, |p| p.amount_minor),
+            currency: update.price.map_or(product.currency, |p| p.currency),
+            active: update.active.unwrap_or(product.active),
+            updated_at: chrono::offset::Utc::now().naive_utc(),
+            ..product
+        };
+
+        diesel::update(products.filter(product_id.eq(pid)))
+            .set((
+                name.eq(&updated.name),
+                price_minor.eq(updated.price_minor),
+                currency.eq(updated.currency),
+                active.eq(updated.active),
+                updated_at.eq(updated.updated_at),
+            ))
+            .execute(conn)?;
+
+        Ok(updated.into())
+    })
+}
+
+/// Change stock of product manually. Stock can not go below 0, such change is rejected with 409.
+pub fn adjust_stock(
+    pid: Uuid,
+    request: &models::StockAdjustmentRequest,
+    admin_id: Uuid,
+    conn: &PgConnection,
+) -> Result<ProductDetails, AppError> {
+    conn.transaction::<_, AppError, _>(|| {
+        // Make sure product exists, inventory only reports products which are short of stock.
+        find_product_by_id(pid, true, conn)?;
+
+        let change = StockChange {
+            product_id: pid,
+            delta: request.delta,
+        };
+        inventory::apply_stock_changes(&[change], None, admin_id, Some(&request.reason), conn)?;
+
+        find_product_by_id(pid, true, conn)
+    })
+}
+
+/// Audit trail of stock changes of product, latest first.
+pub fn find_stock_adjustments(pid: Uuid, conn: &PgConnection) -> Result<Vec<StockAdjustment>, AppError> {
+    find_product_by_id(pid, true, conn)?;
+    inventory::find_stock_adjustments(pid, conn)
+}
@@ -0,0 +1,131 @@
+//! Diesel does not support async operations, i.e. diesel operations are blocking, so we have to run it in separate threads using the web::block
+//! function which offloads blocking code (like Diesel's) in order to not block the server's thread.
+
+use actix_web::http::header;
+use actix_web::{get, patch, post, web, HttpResponse};
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::errors::AppError;
+use crate::users::auth::AuthenticatedUser;
+
+#[path = "./product_actions.rs"] mod actions;
+
+type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Lists products which can be ordered. Admins see inactive products too.
+#[get("/api/v1/products")]
+pub async fn get_products(user: AuthenticatedUser, pool: web::Data<DbPool>) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let list = web::block(move || actions::find_all_products(user.is_admin, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(list))
+}
+
+/// Finds product by UID. Inactive products are only found by admins.
+#[get("/api/v1/products/{product_id}")]
+pub async fn get_product_by_id(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    product_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    let product_id = product_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let product = web::block(move || actions::find_product_by_id(product_id, user.is_admin, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+/// Adds product to catalog. Admin only.
+#[post("/api/v1/products")]
+pub async fn create_product(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    body: web::Json<actions::models::NewProduct>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let product = web::block(move || actions::insert_new_product(&body, &conn)).await?;
+
+    Ok(HttpResponse::Created()
+        .header(header::LOCATION, format!("/api/v1/products/{}", product.product_id))
+        .json(product))
+}
+
+/// Changes name, price or active flag of product. Admin only.
+#[patch("/api/v1/products/{product_id}")]
+pub async fn update_product(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    product_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::ProductUpdate>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    let product_id = product_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let product = web::block(move || actions::update_product(product_id, &body, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+/// Changes stock of product by given delta, with reason recorded in audit trail. Admin only.
+#[post("/api/v1/products/{product_id}/stock-adjustments")]
+pub async fn adjust_product_stock(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    product_uid: web::Path<Uuid>,
+    body: web::Json<actions::models::StockAdjustmentRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
+
+    let product_id = product_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let product = web::block(move || actions::adjust_stock(product_id, &body, user.user_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+/// Lists every change of stock of product (orders and manual adjustments), latest first. Admin only.
+#[get("/api/v1/products/{product_id}/stock-adjustments")]
+pub async fn get_product_stock_adjustments(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    product_uid: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    user.require_admin()?;
+
+    let conn = pool.get()?;
+
+    let product_id = product_uid.into_inner();
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let adjustments = web::block(move || actions::find_stock_adjustments(product_id, &conn)).await?;
+
+    Ok(HttpResponse::Ok().json(adjustments))
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+use crate::money::{Currency, Money};
+use crate::schema::products;
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct Product {
+    pub product_id: uuid::Uuid,
+    // Stock keeping unit, unique code of product. Can not be changed once product is created.
+    pub sku: String,
+    pub name: String,
+    pub price_minor: i64,
+    pub currency: Currency,
+    // Inactive products are hidden from customers and can not be ordered.
+    pub active: bool,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    // Quantity which can still be ordered. Only changed through `inventory::apply_stock_changes`.
+    pub stock_available: i32
+}
+
+impl Product {
+    pub fn price(&self) -> Money {
+        Money::new(self.price_minor, self.currency)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductDetails {
+    pub product_id: uuid::Uuid,
+    pub sku: String,
+    pub name: String,
+    pub price: Money,
+    pub active: bool,
+    pub stock_available: i32,
+}
+
+impl From<Product> for ProductDetails {
+    fn from(product: Product) -> Self {
+        ProductDetails {
+            price: product.price(),
+            product_id: product.product_id,
+            sku: product.sku,
+            name: product.name,
+            active: product.active,
+            stock_available: product.stock_available,
+        }
+    }
+}
+
+// Length limits match varchar columns of products table.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct NewProduct {
+    #[validate(custom = "validate_sku")]
+    pub sku: String,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub name: String,
+    #[validate(custom = "crate::payload::validate_non_negative")]
+    pub price: Money,
+    #[serde(default = "crate::payload::default_active")]
+    pub active: bool,
+}
+
+/// Partial update of product. Fields which are absent are left unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ProductUpdate {
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
+    pub name: Option<String>,
+    #[validate(custom = "crate::payload::validate_non_negative")]
+    pub price: Option<Money>,
+    pub active: Option<bool>,
+}
+
+/// Manual change of stock by admin, e.g. new delivery (positive) or damaged goods (negative).
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct StockAdjustmentRequest {
+    #[validate(range(min = -1000000, max = 1000000, message = "must be between -1000000 and 1000000"))]
+    #[validate(custom = "validate_non_zero")]
+    pub delta: i32,
+    #[validate(length(min = 1, max = 500, message = "must be between 1 and 500 characters"))]
+    pub reason: String,
+}
+
+fn validate_non_zero(delta: i32) -> Result<(), ValidationError> {
+    if delta != 0 {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("non_zero");
+        err.message = Some("must not be 0".into());
+        Err(err)
+    }
+}
+
+fn validate_sku(sku: &str) -> Result<(), ValidationError> {
+    let valid_chars = sku.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !sku.is_empty() && sku.len() <= 64 && valid_chars {
+        Ok(())
+    } else {
+        let mut err = ValidationError::new("sku");
+        err.message = Some("must be 1 to 64 letters, digits, '-', '_' or '.'".into());
+        Err(err)
+    }
+}
@@ -1,11 +1,108 @@
+table! {
+    addresses (address_id) {
+        address_id -> Uuid,
+        user_id -> Uuid,
+        label -> Nullable<Varchar>,
+        full_name -> Varchar,
+        line1 -> Varchar,
+        line2 -> Nullable<Varchar>,
+        city -> Varchar,
+        region -> Nullable<Varchar>,
+        postal_code -> Varchar,
+        country -> Varchar,
+        phone -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    cart_items (user_id, product_id) {
+        user_id -> Uuid,
+        product_id -> Uuid,
+        qty -> Int4,
+        added_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    coupons (coupon_id) {
+        coupon_id -> Uuid,
+        code -> Varchar,
+        kind -> Varchar,
+        percent_off -> Nullable<Int4>,
+        amount_off_minor -> Nullable<Int8>,
+        currency -> Nullable<Varchar>,
+        min_order_total_minor -> Nullable<Int8>,
+        expires_at -> Nullable<Timestamptz>,
+        max_uses -> Nullable<Int4>,
+        max_uses_per_user -> Nullable<Int4>,
+        times_used -> Int4,
+        active -> Bool,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    idempotency_keys (user_id, idempotency_key) {
+        user_id -> Uuid,
+        idempotency_key -> Varchar,
+        request_hash -> Varchar,
+        order_id -> Uuid,
+        response_status -> Int4,
+        response_body -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    order_addresses (order_id, kind) {
+        order_id -> Uuid,
+        kind -> Varchar,
+        full_name -> Varchar,
+        line1 -> Varchar,
+        line2 -> Nullable<Varchar>,
+        city -> Varchar,
+        region -> Nullable<Varchar>,
+        postal_code -> Varchar,
+        country -> Varchar,
+        phone -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    order_discounts (discount_id) {
+        discount_id -> Uuid,
+        order_id -> Uuid,
+        coupon_id -> Uuid,
+        user_id -> Uuid,
+        code -> Varchar,
+        amount_minor -> Int8,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     order_items (item_id) {
         item_id -> Uuid,
         order_id -> Uuid,
         description -> Varchar,
         qty -> Int4,
-        price -> Int4,
         created_at -> Timestamptz,
+        price_minor -> Int8,
+        currency -> Varchar,
+        product_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    order_status_changes (change_id) {
+        change_id -> Uuid,
+        order_id -> Uuid,
+        from_status -> Nullable<Varchar>,
+        to_status -> Varchar,
+        changed_at -> Timestamptz,
     }
 }
 
@@ -15,6 +112,61 @@ table! {
         user_id -> Uuid,
         note -> Nullable<Varchar>,
         created_at -> Timestamptz,
+        status -> Varchar,
+        cancellation_reason -> Nullable<Varchar>,
+        cancelled_at -> Nullable<Timestamptz>,
+        version -> Int4,
+        currency -> Varchar,
+    }
+}
+
+table! {
+    products (product_id) {
+        product_id -> Uuid,
+        sku -> Varchar,
+        name -> Varchar,
+        price_minor -> Int8,
+        currency -> Varchar,
+        active -> Bool,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        stock_available -> Int4,
+    }
+}
+
+table! {
+    refresh_tokens (token_id) {
+        token_id -> Uuid,
+        user_id -> Uuid,
+        family_id -> Uuid,
+        token_hash -> Varchar,
+        expires_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    revoked_tokens (jti) {
+        jti -> Uuid,
+        user_id -> Uuid,
+        expires_at -> Timestamptz,
+        revoked_at -> Timestamptz,
+    }
+}
+
+table! {
+    stock_adjustments (adjustment_id) {
+        adjustment_id -> Uuid,
+        product_id -> Uuid,
+        delta -> Int4,
+        stock_after -> Int4,
+        kind -> Varchar,
+        order_id -> Nullable<Uuid>,
+        user_id -> Uuid,
+        reason -> Nullable<Varchar>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -26,14 +178,44 @@ table! {
         email -> Varchar,
         password -> Varchar,
         created_at -> Timestamptz,
+        token_generation -> Int4,
+        is_admin -> Bool,
+        version -> Int4,
     }
 }
 
+joinable!(addresses -> users (user_id));
+joinable!(cart_items -> products (product_id));
+joinable!(cart_items -> users (user_id));
+joinable!(idempotency_keys -> orders (order_id));
+joinable!(idempotency_keys -> users (user_id));
+joinable!(order_addresses -> orders (order_id));
+joinable!(order_discounts -> coupons (coupon_id));
+joinable!(order_discounts -> orders (order_id));
+joinable!(order_discounts -> users (user_id));
 joinable!(order_items -> orders (order_id));
+joinable!(order_items -> products (product_id));
+joinable!(order_status_changes -> orders (order_id));
 joinable!(orders -> users (user_id));
+joinable!(refresh_tokens -> users (user_id));
+joinable!(revoked_tokens -> users (user_id));
+joinable!(stock_adjustments -> orders (order_id));
+joinable!(stock_adjustments -> products (product_id));
+joinable!(stock_adjustments -> users (user_id));
 
 allow_tables_to_appear_in_same_query!(
+    addresses,
+    cart_items,
+    coupons,
+    idempotency_keys,
+    order_addresses,
+    order_discounts,
     order_items,
+    order_status_changes,
     orders,
+    products,
+    refresh_tokens,
+    revoked_tokens,
+    stock_adjustments,
     users,
 );
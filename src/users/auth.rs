@@ -0,0 +1,109 @@
+//! Authentication of incoming requests.
+//!
+//! Handlers which need authenticated user simply declare `user: AuthenticatedUser` argument. Extractor reads jwt
+//! from 'Authorization: Bearer' or 'access_token' header, verifies it and checks in db that user exists before
+//! handler is called.
+
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{web, FromRequest, HttpRequest};
+use diesel::prelude::*;
+use diesel::r2d2::{self, ConnectionManager};
+use futures::future::LocalBoxFuture;
+use uuid::Uuid;
+
+use crate::config::{JwtKeys, TokenHeader, TokenHeaderPrecedence};
+use crate::errors::AppError;
+use crate::users::revocation::RevocationStore;
+
+#[path = "./user_actions.rs"] mod actions;
+
+type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
+
+/// Identity of user who made the request.
+#[derive(Debug, Clone, Copy)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    // id and expiry (unix seconds) of access token request was made with, needed to revoke it on logout
+    pub token_id: Uuid,
+    pub token_expires_at: i64,
+    pub is_admin: bool,
+}
+
+impl AuthenticatedUser {
+    /// Reject request with 403 unless it is made by admin.
+    pub fn require_admin(&self) -> Result<(), AppError> {
+        if self.is_admin {
+            Ok(())
+        } else {
+            Err(AppError::Forbidden("Only admins are allowed to do this.".to_owned()))
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<web::Data<DbPool>>().cloned();
+        let keys = req.app_data::<web::Data<JwtKeys>>().cloned();
+        let revocations = req.app_data::<web::Data<RevocationStore>>().cloned();
+        let precedence = req
+            .app_data::<web::Data<TokenHeaderPrecedence>>()
+            .map(|p| *p.get_ref())
+            .unwrap_or_default();
+        let token = token_from_headers(req, precedence);
+
+        Box::pin(async move {
+            let (pool, keys, revocations) = match (pool, keys, revocations) {
+                (Some(pool), Some(keys), Some(revocations)) => (pool, keys, revocations),
+                _ => return Err(AppError::Internal("authentication app data is not configured".to_owned())),
+            };
+
+            // Fail fast without taking db connection when there is no token at all.
+            if token.is_none() {
+                return Err(AppError::Unauthorized("Provide proper access token".to_owned()));
+            }
+
+            let conn = pool.get()?;
+
+            // use web::block to offload blocking Diesel code without blocking server thread
+            let (claims, user) =
+                web::block(move || actions::authenticate_request(token, &keys, &revocations, &conn)).await?;
+
+            Ok(AuthenticatedUser {
+                user_id: claims.user_id,
+                token_id: claims.jti,
+                token_expires_at: claims.exp,
+                is_admin: user.is_admin,
+            })
+        })
+    }
+}
+
+/// Read access token from request headers in configured order. Header which is present but not usable
+/// (e.g. 'Authorization: Basic ...') is skipped.
+fn token_from_headers(req: &HttpRequest, precedence: TokenHeaderPrecedence) -> Option<String> {
+    precedence.0.iter().find_map(|source| match source {
+        TokenHeader::Authorization => req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                let mut parts = v.splitn(2, ' ');
+                match (parts.next(), parts.next()) {
+                    (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("bearer") => Some(token.trim()),
+                    _ => None,
+                }
+            })
+            .filter(|token| !token.is_empty())
+            .map(str::to_owned),
+        TokenHeader::AccessToken => req
+            .headers()
+            .get("access_token")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned),
+    })
+}
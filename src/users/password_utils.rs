@@ -0,0 +1,59 @@
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use crate::config::PasswordHashing;
+
+/// Outcome of checking a password against the value stored in `users.password`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    /// Password matches an Argon2 PHC string.
+    Valid,
+    /// Password matches a legacy plain text row. Caller should rewrite it as a hash.
+    ValidLegacy,
+    Invalid,
+}
+
+/// Build Argon2id hasher with given costs.
+fn hasher(params: &Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+}
+
+/// Hash plain text password with a random salt and configured costs. Returned value is PHC string which fits in
+/// `users.password` column.
+pub fn hash_password(plain: &str, hashing: &PasswordHashing) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = hasher(hashing.params()).hash_password(plain.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+/// Verify plain text password against stored value.
+/// Rows created before password hashing was introduced hold plain text, which is detected by absence of PHC prefix.
+pub fn verify_password(plain: &str, stored: &str) -> PasswordCheck {
+    if !is_password_hash(stored) {
+        return if constant_time_eq(plain.as_bytes(), stored.as_bytes()) {
+            PasswordCheck::ValidLegacy
+        } else {
+            PasswordCheck::Invalid
+        };
+    }
+
+    // Parameters are read from PHC string itself, so hashes created with older costs still verify.
+    match PasswordHash::new(stored) {
+        Ok(parsed) if hasher(&Params::default()).verify_password(plain.as_bytes(), &parsed).is_ok() => PasswordCheck::Valid,
+        _ => PasswordCheck::Invalid,
+    }
+}
+
+/// Whether stored value is Argon2 PHC string rather than legacy plain text.
+fn is_password_hash(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+/// Compare bytes without returning early on first mismatch.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,101 @@
+//! Store of access tokens revoked before their expiry (i.e. logged out).
+//!
+//! `revoked_tokens` table is the source of truth. Every app instance keeps revoked token ids in memory and
+//! pulls newly revoked ones from db at most once per `SYNC_INTERVAL_SECONDS`, so that checking a token on
+//! each request does not need a db round trip. Tokens revoked by this instance are visible immediately,
+//! ones revoked by other instances after next sync.
+//!
+//! `revoked_at` is stamped by db and sync compares it against db time too, so that clocks of app instances do not
+//! need to agree.
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use uuid::Uuid;
+
+static SYNC_INTERVAL_SECONDS: i64 = 5;
+
+#[derive(Default)]
+struct Cache {
+    // jti -> token expiry. Entries are dropped once token would have expired anyway.
+    revoked: HashMap<Uuid, NaiveDateTime>,
+    // Local time of last sync, decides when next one is due.
+    synced_at: Option<NaiveDateTime>,
+    // Db time of last sync, rows revoked since then are pulled by next one.
+    db_synced_at: Option<NaiveDateTime>,
+}
+
+#[derive(Default)]
+pub struct RevocationStore {
+    cache: RwLock<Cache>,
+}
+
+impl RevocationStore {
+    /// Whether token with given jti has been revoked.
+    pub fn is_revoked(&self, token_jti: Uuid, conn: &PgConnection) -> QueryResult<bool> {
+        let now = chrono::offset::Utc::now().naive_utc();
+
+        let needs_sync = {
+            let cache = self.cache.read().unwrap();
+            match cache.synced_at {
+                Some(synced_at) => now - synced_at >= Duration::seconds(SYNC_INTERVAL_SECONDS),
+                None => true,
+            }
+        };
+
+        if needs_sync {
+            self.sync(now, conn)?;
+        }
+
+        Ok(self.cache.read().unwrap().revoked.contains_key(&token_jti))
+    }
+
+    /// Revoke token with given jti. Revoking already revoked token is no-op.
+    pub fn revoke(&self, token_jti: Uuid, uid: Uuid, token_expires_at: NaiveDateTime, conn: &PgConnection) -> QueryResult<()> {
+        use crate::schema::revoked_tokens::dsl::*;
+
+        diesel::insert_into(revoked_tokens)
+            .values((
+                jti.eq(token_jti),
+                user_id.eq(uid),
+                expires_at.eq(token_expires_at),
+                revoked_at.eq(diesel::dsl::now),
+            ))
+            .on_conflict(jti)
+            .do_nothing()
+            .execute(conn)?;
+
+        self.cache.write().unwrap().revoked.insert(token_jti, token_expires_at);
+
+        Ok(())
+    }
+
+    /// Pull tokens revoked since last sync and forget expired ones.
+    fn sync(&self, now: NaiveDateTime, conn: &PgConnection) -> QueryResult<()> {
+        use crate::schema::revoked_tokens::dsl::*;
+
+        let since = self.cache.read().unwrap().db_synced_at;
+        let db_now = diesel::select(diesel::dsl::now).get_result::<NaiveDateTime>(conn)?;
+
+        let mut query = revoked_tokens
+            .select((jti, expires_at))
+            .filter(expires_at.gt(db_now))
+            .into_boxed();
+
+        if let Some(since) = since {
+            // Overlap a little with previous sync so that rows committed while it ran are not missed.
+            query = query.filter(revoked_at.ge(since - Duration::seconds(SYNC_INTERVAL_SECONDS)));
+        }
+
+        let rows: Vec<(Uuid, NaiveDateTime)> = query.load(conn)?;
+
+        let mut cache = self.cache.write().unwrap();
+        cache.revoked.retain(|_, exp| *exp > now);
+        cache.revoked.extend(rows);
+        cache.synced_at = Some(now);
+        cache.db_synced_at = Some(db_now);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,134 @@
+#[path = "./user_models.rs"] mod models;
+
+use chrono::Duration;
+use diesel::prelude::*;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+static REFRESH_TOKEN_LIFETIME_DAYS: i64 = 30;
+
+/// Result of exchanging refresh token for new one.
+pub enum RefreshOutcome {
+    /// Token was valid. It is now marked used and new token from same family is issued.
+    Rotated { user_id: Uuid, refresh_token: String },
+    /// Token is unknown, expired or revoked.
+    Invalid,
+    /// Token was already exchanged before. Whole family has been revoked as token is likely stolen.
+    Reused,
+}
+
+/// Hex encoded SHA-256 of token. Only this is stored in db.
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Insert new refresh token in given family and return opaque token to be handed over to client.
+fn insert_refresh_token(uid: Uuid, family: Uuid, conn: &PgConnection) -> QueryResult<String> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+
+    let now = chrono::offset::Utc::now().naive_utc();
+    let new_token = models::RefreshToken {
+        token_id: Uuid::new_v4(),
+        user_id: uid,
+        family_id: family,
+        token_hash: hash_token(&token),
+        expires_at: now + Duration::days(REFRESH_TOKEN_LIFETIME_DAYS),
+        used_at: None,
+        revoked_at: None,
+        created_at: now,
+    };
+
+    diesel::insert_into(refresh_tokens).values(&new_token).execute(conn)?;
+
+    Ok(token)
+}
+
+/// Issue refresh token starting new family. Called on register and login.
+pub fn issue_refresh_token(uid: Uuid, conn: &PgConnection) -> Result<String, AppError> {
+    Ok(insert_refresh_token(uid, Uuid::new_v4(), conn)?)
+}
+
+/// Exchange refresh token for new one. Token row is locked so that concurrent refreshes with same token
+/// can not both succeed.
+pub fn rotate_refresh_token(token: &str, conn: &PgConnection) -> Result<RefreshOutcome, AppError> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    let hashed = hash_token(token);
+
+    // Revocation on reuse is returned as Ok outcome (not Err) so that transaction is committed.
+    conn.transaction::<_, AppError, _>(|| {
+        let existing = refresh_tokens
+            .filter(token_hash.eq(&hashed))
+            .for_update()
+            .first::<models::RefreshToken>(conn)
+            .optional()?;
+
+        let existing = match existing {
+            Some(t) => t,
+            None => return Ok(RefreshOutcome::Invalid),
+        };
+
+        let now = chrono::offset::Utc::now().naive_utc();
+
+        if existing.revoked_at.is_some() || existing.expires_at <= now {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        if existing.used_at.is_some() {
+            diesel::update(refresh_tokens.filter(family_id.eq(existing.family_id)).filter(revoked_at.is_null()))
+                .set(revoked_at.eq(now))
+                .execute(conn)?;
+            return Ok(RefreshOutcome::Reused);
+        }
+
+        diesel::update(refresh_tokens.filter(token_id.eq(existing.token_id)))
+            .set(used_at.eq(now))
+            .execute(conn)?;
+
+        let new_token = insert_refresh_token(existing.user_id, existing.family_id, conn)?;
+
+        Ok(RefreshOutcome::Rotated {
+            user_id: existing.user_id,
+            refresh_token: new_token,
+        })
+    })
+}
+
+/// Revoke refresh token of user `uid` along with all tokens rotated from same login. Unknown token, or token of other
+/// user, is ignored.
+pub fn revoke_refresh_token_family(token: &str, uid: Uuid, conn: &PgConnection) -> Result<(), AppError> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    let family = refresh_tokens
+        .select(family_id)
+        .filter(token_hash.eq(hash_token(token)))
+        .filter(user_id.eq(uid))
+        .first::<Uuid>(conn)
+        .optional()?;
+
+    if let Some(family) = family {
+        diesel::update(refresh_tokens.filter(family_id.eq(family)).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(chrono::offset::Utc::now().naive_utc()))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// Revoke every refresh token of user.
+pub fn revoke_all_refresh_tokens(uid: Uuid, conn: &PgConnection) -> Result<(), AppError> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    diesel::update(refresh_tokens.filter(user_id.eq(uid)).filter(revoked_at.is_null()))
+        .set(revoked_at.eq(chrono::offset::Utc::now().naive_utc()))
+        .execute(conn)?;
+
+    Ok(())
+}
@@ -1,41 +1,79 @@
 use chrono::Utc;
+use diesel::PgConnection;
 use jsonwebtoken::{EncodingKey, DecodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
 
-static ONE_WEEK: i64 = 60 * 60 * 24 * 7; // in seconds
-// Todo: To load this secret from environment variable.
-static KEY: &[u8] = b"some_secret_key";
+use crate::config::JwtKeys;
+use crate::errors::AppError;
+use crate::users::revocation::RevocationStore;
+
+// Access tokens are short lived. Clients get new one with refresh token.
+pub static ACCESS_TOKEN_LIFETIME: i64 = 60 * 15; // in seconds
 
 #[derive(Serialize, Deserialize)]
-struct UserToken {
+pub struct UserToken {
     // issued at
     pub iat: i64,
     // expiration
     pub exp: i64,
+    // unique token id, used to revoke single token on logout
+    pub jti: uuid::Uuid,
+    // user's token generation at the time of issue, see users.token_generation
+    pub gen: i32,
     // data
     pub user_id: uuid::Uuid,
 }
 
-/// Create jwt token by making use of user id.
-pub fn generate_jwt(uid: uuid::Uuid) -> String {
+/// Create jwt token by making use of user id. Token is signed with active key and its kid is set in header.
+pub fn generate_jwt(keys: &JwtKeys, uid: uuid::Uuid, token_generation: i32) -> String {
     let now = Utc::now().timestamp_nanos() / 1_000_000_000; // nanosecond -> second
     let payload = UserToken {
         iat: now,
-        exp: now + ONE_WEEK,
+        exp: now + ACCESS_TOKEN_LIFETIME,
+        jti: uuid::Uuid::new_v4(),
+        gen: token_generation,
         user_id: uid,
     };
 
+    let (kid, secret) = keys.signing_key();
+    let header = Header {
+        kid: Some(kid.to_owned()),
+        ..Header::default()
+    };
+
     jsonwebtoken::encode(
-        &Header::default(),
+        &header,
         &payload,
-        &EncodingKey::from_secret(KEY),
+        &EncodingKey::from_secret(secret),
     )
     .unwrap()
 }
 
+/// Verify token with key referred by kid in its header, check that it is not revoked and return its claims.
+/// Any key present in keyring is accepted so that tokens signed before key rotation remain valid.
+/// Token generation is not checked here as it needs user row, see `authenticate_request`.
+pub fn decode_jwt(
+    keys: &JwtKeys,
+    revocations: &RevocationStore,
+    token: &str,
+    conn: &PgConnection,
+) -> Result<UserToken, AppError> {
+    let invalid = |_| AppError::InvalidToken("Provide proper access token".to_owned());
+
+    let kid = jsonwebtoken::decode_header(token)
+        .map_err(invalid)?
+        .kid
+        .ok_or_else(|| AppError::InvalidToken("kid is missing in access token header.".to_owned()))?;
+    let secret = keys
+        .verification_key(&kid)
+        .ok_or_else(|| AppError::InvalidToken("Access token is signed with unknown key.".to_owned()))?;
+
+    let token_data = jsonwebtoken::decode::<UserToken>(token, &DecodingKey::from_secret(secret), &Validation::default())
+        .map_err(invalid)?;
+
+    if revocations.is_revoked(token_data.claims.jti, conn)? {
+        return Err(AppError::InvalidToken("Access token has been revoked.".to_owned()));
+    }
 
-pub fn decode_jwt_and_get_user_id(token: &str) -> Result<uuid::Uuid, Box<dyn Error>> {
-    let token_data = jsonwebtoken::decode::<UserToken>(token, &DecodingKey::from_secret(&KEY), &Validation::default())?;
-    Ok(token_data.claims.user_id)
+    Ok(token_data.claims)
 }
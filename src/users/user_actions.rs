@@ -1,24 +1,27 @@
 #[path = "./user_models.rs"] pub mod models;
 
-use actix_web::http::{HeaderValue, StatusCode};
 use diesel::prelude::*;
 
 use uuid::Uuid;
 
+use crate::config::{JwtKeys, PasswordHashing};
+use crate::errors::AppError;
+use crate::users::revocation::RevocationStore;
+
 #[path = "./token_utils.rs"] mod token_utils;
+#[path = "./password_utils.rs"] mod password_utils;
 
 /// Find user by user_id. If not found then return None.
 pub fn find_user_by_uid(
     uid: Uuid,
     conn: &PgConnection,
-) -> Result<Option<models::User>, StatusCode> {
+) -> Result<Option<models::User>, AppError> {
     use crate::schema::users::dsl::*;
 
     let user = users
         .filter(user_id.eq(uid))
         .first::<models::User>(conn)
-        .optional()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .optional()?;
 
     Ok(user)
 }
@@ -27,7 +30,7 @@ pub fn find_user_by_uid(
 pub fn find_user_by_email(
     email_str: &str,
     conn: &PgConnection,
-) -> Result<Option<models::User>, StatusCode> {
+) -> Result<Option<models::User>, AppError> {
     // It is common when using Diesel with Actix web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
@@ -36,55 +39,121 @@ pub fn find_user_by_email(
     let user = users
         .filter(email.eq(email_str))
         .first::<models::User>(conn)
-        .optional()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .optional()?;
 
     Ok(user)
 }
 
-/// Extract user_id from jwt token and verify in db that user by that user_id exists.
-/// This should ideally have been authentication middleware of actix. Todo.
+/// Find user by email and verify password. If user is not found or password does not match then return None.
+/// Legacy rows which still hold plain text password are rewritten with Argon2id hash on first successful login.
+/// Unknown email is checked against dummy hash, so that it takes as long as wrong password.
+pub fn find_user_by_credentials(
+    email_str: &str,
+    passwd: &str,
+    hashing: &PasswordHashing,
+    conn: &PgConnection,
+) -> Result<Option<models::User>, AppError> {
+    let user = match find_user_by_email(email_str, conn)? {
+        Some(user) => user,
+        None => {
+            password_utils::verify_password(passwd, hashing.dummy_hash());
+            return Ok(None);
+        }
+    };
+
+    match password_utils::verify_password(passwd, &user.password) {
+        password_utils::PasswordCheck::Valid => Ok(Some(user)),
+        password_utils::PasswordCheck::ValidLegacy => {
+            let hashed = password_utils::hash_password(passwd, hashing)
+                .map_err(|e| AppError::Internal(format!("couldn't hash password: {}", e)))?;
+            update_user_password(user.user_id, &hashed, conn)?;
+            Ok(Some(models::User { password: hashed, version: user.version + 1, ..user }))
+        }
+        password_utils::PasswordCheck::Invalid => Ok(None),
+    }
+}
+
+/// Replace stored password value of user. Value should already be hashed.
+pub fn update_user_password(
+    uid: Uuid,
+    hashed_passwd: &str,
+    conn: &PgConnection,
+) -> Result<(), AppError> {
+    use crate::schema::users::dsl::*;
+
+    diesel::update(users.filter(user_id.eq(uid)))
+        .set((password.eq(hashed_passwd), version.eq(version + 1)))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Verify jwt token (already taken out of request headers), check in db that user by user_id in it exists and
+/// that token was not invalidated by "log out all sessions". Returns claims of the token and the user.
+/// Used by `AuthenticatedUser` extractor, handlers should not need to call this directly.
 pub fn authenticate_request(
-    header: Option<HeaderValue>,
+    token: Option<String>,
+    keys: &JwtKeys,
+    revocations: &RevocationStore,
     conn: &PgConnection,
-) -> Result<uuid::Uuid, StatusCode> {
+) -> Result<(token_utils::UserToken, models::User), AppError> {
+
+    let jwt_str = token.ok_or_else(|| AppError::Unauthorized("Provide proper access token".to_owned()))?;
 
-    let v = header.ok_or(StatusCode::UNAUTHORIZED)?;
-    let jwt_str = v.to_str().map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let claims = token_utils::decode_jwt(keys, revocations, &jwt_str, conn)?;
 
-    let user_id =
-        token_utils::decode_jwt_and_get_user_id(jwt_str).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let user = find_user_by_uid(claims.user_id, conn)?
+        .ok_or_else(|| AppError::NotFound("User in access_token is not found in db.".to_owned()))?;
 
-    let user_option =
-        find_user_by_uid(user_id, conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if user.token_generation != claims.gen {
+        return Err(AppError::InvalidToken("Access token has been revoked.".to_owned()));
+    }
 
-    user_option.map(|u| u.user_id).ok_or(StatusCode::NOT_FOUND)
+    Ok((claims, user))
 }
 
+/// Increment token generation of user so that every token issued so far stops working.
+pub fn bump_token_generation(uid: Uuid, conn: &PgConnection) -> Result<(), AppError> {
+    use crate::schema::users::dsl::*;
 
-/// Insert new user in db as part of new user registration.
+    diesel::update(users.filter(user_id.eq(uid)))
+        .set((token_generation.eq(token_generation + 1), version.eq(version + 1)))
+        .execute(conn)?;
+
+    Ok(())
+}
+
+
+/// Insert new user in db as part of new user registration. Password is stored as Argon2id hash.
 pub fn insert_new_user(
     first_n: &str,
     last_n: &str,
     email_str: &str,
     passwd: &str,
+    hashing: &PasswordHashing,
     conn: &PgConnection,
-) -> Result<models::User, StatusCode> {
+) -> Result<models::User, AppError> {
     // It is common when using Diesel with Actix web to import schema-related
     // modules inside a function's scope (rather than the normal module's scope)
     // to prevent import collisions and namespace pollution.
     use crate::schema::users::dsl::*;
 
+    let hashed_passwd = password_utils::hash_password(passwd, hashing)
+        .map_err(|e| AppError::Internal(format!("couldn't hash password: {}", e)))?;
+
     let new_user = models::User {
         user_id: Uuid::new_v4(),
         first_name: first_n.to_owned(),
         last_name: last_n.to_owned(),
         email: email_str.to_owned(),
-        password: passwd.to_owned(),
+        password: hashed_passwd,
         created_at: chrono::offset::Utc::now().naive_utc(),
+        token_generation: 0,
+        is_admin: false,
+        version: 1,
     };
 
-    diesel::insert_into(users).values(&new_user).execute(conn).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    diesel::insert_into(users).values(&new_user).execute(conn)?;
 
     Ok(new_user)
 }
\ No newline at end of file
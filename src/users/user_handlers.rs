@@ -3,26 +3,42 @@
 //! Diesel does not support tokio, so we have to run it in separate threads using the web::block
 //! function which offloads blocking code (like Diesel's) in order to not block the server's thread.
 
-use actix_web::{
-    error::{
-        BlockingError, ErrorConflict, ErrorForbidden, ErrorInternalServerError, ErrorNotFound,
-    },
-    http::StatusCode,
-};
-use actix_web::{post, web, Error, HttpResponse};
+use actix_web::{post, web, HttpResponse};
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use serde::Serialize;
+use validator::Validate;
 
 
 #[path = "./user_models.rs"] mod models;
 #[path = "./user_actions.rs"] mod actions;
 
+#[path = "./session_actions.rs"] mod session_actions;
 #[path = "./token_utils.rs"] mod token_utils;
 
+use crate::config::{JwtKeys, PasswordHashing};
+use crate::errors::{AppError, FieldError};
+use crate::users::auth::AuthenticatedUser;
+use crate::users::revocation::RevocationStore;
+
 #[derive(Debug, Clone, Serialize)]
 struct JWTResponse {
+    // short lived access token
     token: String,
+    // access token lifetime in seconds
+    expires_in: i64,
+    // opaque token to get new access token from refresh route
+    refresh_token: String,
+}
+
+impl JWTResponse {
+    fn new(keys: &JwtKeys, user: &actions::models::User, refresh_token: String) -> JWTResponse {
+        JWTResponse {
+            token: token_utils::generate_jwt(keys, user.user_id, user.token_generation),
+            expires_in: token_utils::ACCESS_TOKEN_LIFETIME,
+            refresh_token,
+        }
+    }
 }
 
 type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
@@ -31,77 +47,155 @@ type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 #[post("/api/v1/auth/register")]
 async fn register_user(
     pool: web::Data<DbPool>,
+    keys: web::Data<JwtKeys>,
+    hashing: web::Data<PasswordHashing>,
     body: web::Json<models::NewUser>,
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().map_err(|_| ErrorInternalServerError("couldn't get db connection from pool. Please retry."))?;
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
 
     // use web::block to offload blocking Diesel code without blocking server thread
-    let user = web::block(move || {
+    let (user, refresh_token) = web::block(move || {
         // Check if user with email id is already present. If yes, then return error.
         let user_option = actions::find_user_by_email(&body.email, &conn)?;
 
         if user_option.is_some() {
-            return Err(StatusCode::CONFLICT);
+            return Err(AppError::Conflict("User with email already present".to_owned()));
         }
 
-        actions::insert_new_user(
+        let user = actions::insert_new_user(
             &body.first_name,
             &body.last_name,
             &body.email,
-            // WARNING: Never put plain text password in db. Always encrypt them. This is just for demostration purpose.
+            // Password gets hashed before it is stored.
             &body.password,
+            &hashing,
             &conn,
-        )
-    })
-    .await
-    .map_err(|e| match e {
-        BlockingError::Error(StatusCode::CONFLICT) => {
-            ErrorConflict("User with email already present")
-        }
-        BlockingError::Error(StatusCode::NOT_FOUND) => {
-            ErrorNotFound("User could not be found to create new order.")
-        }
-        _ => ErrorInternalServerError("Something unexpected happened. Please retry"),
-    })?;
+        )?;
 
-    let token_str = token_utils::generate_jwt(user.user_id);
+        let refresh_token = session_actions::issue_refresh_token(user.user_id, &conn)?;
+        Ok((user, refresh_token))
+    })
+    .await?;
 
-    Ok(HttpResponse::Ok().json(JWTResponse { token: token_str }))
+    Ok(HttpResponse::Ok().json(JWTResponse::new(&keys, &user, refresh_token)))
 }
 
-/// Verify credentials and return JWT token.
+/// Verify credentials and return JWT token along with refresh token.
 #[post("/api/v1/auth/login")]
 async fn login_user(
     pool: web::Data<DbPool>,
+    keys: web::Data<JwtKeys>,
+    hashing: web::Data<PasswordHashing>,
     body: web::Json<models::UserLogin>
-) -> Result<HttpResponse, Error> {
-    let conn = pool.get().map_err(|_| ErrorInternalServerError("couldn't get db connection from pool. Please retry."))?;
+) -> Result<HttpResponse, AppError> {
+    // Reject invalid payload before any db work.
+    body.validate()?;
+
+    let conn = pool.get()?;
 
     // use web::block to offload blocking Diesel code without blocking server thread
-    let user = web::block(move || {
-        let user_option = actions::find_user_by_email(&body.email, &conn)?;
+    let (user, refresh_token) = web::block(move || {
+        // Legacy plain text password gets upgraded to hash as part of this call.
+        let user_option = actions::find_user_by_credentials(&body.email, &body.password, &hashing, &conn)?;
+
+        // Either user not found for given email or passwords don't match
+        let user = user_option.ok_or_else(|| AppError::Forbidden("email and/or password not correct.".to_owned()))?;
+
+        let refresh_token = session_actions::issue_refresh_token(user.user_id, &conn)?;
+        Ok((user, refresh_token))
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(JWTResponse::new(&keys, &user, refresh_token)))
+}
+
+/// Exchange refresh token for new access token and refresh token.
+/// Each refresh token can be used only once. Replaying already used token revokes all tokens issued from same login.
+#[post("/api/v1/auth/refresh")]
+async fn refresh_access_token(
+    pool: web::Data<DbPool>,
+    keys: web::Data<JwtKeys>,
+    body: web::Json<models::RefreshRequest>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
 
-        if let Some(user) = user_option {
-            if user.password != body.password {
-                // Passwords don't match
-                return Err(StatusCode::FORBIDDEN);
-            } else {
-                return Ok(user);
+    // use web::block to offload blocking Diesel code without blocking server thread
+    let (user, refresh_token) = web::block(move || {
+        let (user_id, refresh_token) = match session_actions::rotate_refresh_token(&body.refresh_token, &conn)? {
+            session_actions::RefreshOutcome::Rotated { user_id, refresh_token } => (user_id, refresh_token),
+            session_actions::RefreshOutcome::Invalid | session_actions::RefreshOutcome::Reused => {
+                return Err(AppError::InvalidToken(
+                    "Refresh token is invalid, expired or already used. Please login again.".to_owned(),
+                ));
             }
-        } else {
-            // User not found for given email
-            return Err(StatusCode::FORBIDDEN);
-        }
+        };
+
+        // User is loaded for current token generation.
+        let user = actions::find_user_by_uid(user_id, &conn)?
+            .ok_or_else(|| AppError::NotFound("User of refresh token is not found in db.".to_owned()))?;
+        Ok((user, refresh_token))
     })
-    .await
-    .map_err(|e| match e {
-        BlockingError::Error(StatusCode::FORBIDDEN) => {
-            ErrorForbidden("email and/or password not correct.")
+    .await?;
+
+    Ok(HttpResponse::Ok().json(JWTResponse::new(&keys, &user, refresh_token)))
+}
+
+/// Log out current session. Access token used for this request stops working immediately, and refresh token
+/// given in body (if any) is revoked along with tokens rotated from it. Body may be left out, but body which is given
+/// must be valid.
+#[post("/api/v1/auth/logout")]
+async fn logout_user(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+    revocations: web::Data<RevocationStore>,
+    body: web::Bytes,
+) -> Result<HttpResponse, AppError> {
+    let refresh_token = if body.is_empty() {
+        None
+    } else {
+        serde_json::from_slice::<models::LogoutRequest>(&body)
+            .map_err(|e| AppError::Validation(vec![FieldError::new("body", &e.to_string())]))?
+            .refresh_token
+    };
+
+    let conn = pool.get()?;
+
+    let token_expires_at = chrono::DateTime::from_timestamp(user.token_expires_at, 0)
+        .map(|dt| dt.naive_utc())
+        .ok_or_else(|| AppError::Internal(format!("invalid token expiry {}", user.token_expires_at)))?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    web::block(move || {
+        revocations.revoke(user.token_id, user.user_id, token_expires_at, &conn)?;
+
+        if let Some(refresh_token) = refresh_token {
+            session_actions::revoke_refresh_token_family(&refresh_token, user.user_id, &conn)?;
         }
-        _ => ErrorInternalServerError("Something unexpected happened. Please retry"),
-    })?;
 
-    let token_str = token_utils::generate_jwt(user.user_id);
+        Ok(())
+    })
+    .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Log out all sessions of user. Every access and refresh token issued so far stops working.
+#[post("/api/v1/auth/logout-all")]
+async fn logout_all_sessions(
+    user: AuthenticatedUser,
+    pool: web::Data<DbPool>,
+) -> Result<HttpResponse, AppError> {
+    let conn = pool.get()?;
+
+    // use web::block to offload blocking Diesel code without blocking server thread
+    web::block(move || {
+        session_actions::revoke_all_refresh_tokens(user.user_id, &conn)?;
+        actions::bump_token_generation(user.user_id, &conn)
+    })
+    .await?;
 
-    Ok(HttpResponse::Ok().json(JWTResponse { token: token_str }))
+    Ok(HttpResponse::NoContent().finish())
 }
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
+use validator::Validate;
 
 use crate::schema::users;
+use crate::schema::refresh_tokens;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize, Queryable, Insertable)]
@@ -10,19 +12,58 @@ pub struct User {
     pub last_name: String,
     pub email: String,
     pub password: String,
-    pub created_at: chrono::NaiveDateTime
+    pub created_at: chrono::NaiveDateTime,
+    // Bumped on "log out all sessions". Tokens carrying older generation are rejected.
+    pub token_generation: i32,
+    // Admins can change status of any order and manage product catalog.
+    pub is_admin: bool,
+    // Incremented on every change of user row.
+    pub version: i32
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Length limits match varchar columns of users table.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct NewUser {
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
     pub first_name: String,
+    #[validate(length(min = 1, max = 255, message = "must be between 1 and 255 characters"))]
     pub last_name: String,
+    #[validate(
+        email(message = "must be a valid email address"),
+        length(max = 255, message = "must be at most 255 characters")
+    )]
     pub email: String,
+    #[validate(length(min = 8, max = 128, message = "must be between 8 and 128 characters"))]
     pub password: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct UserLogin {
+    #[validate(email(message = "must be a valid email address"))]
     pub email: String,
+    #[validate(length(min = 1, message = "must not be empty"))]
     pub password: String,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable)]
+pub struct RefreshToken {
+    pub token_id: uuid::Uuid,
+    pub user_id: uuid::Uuid,
+    pub family_id: uuid::Uuid,
+    pub token_hash: String,
+    pub expires_at: chrono::NaiveDateTime,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogoutRequest {
+    // Refresh token of the session being logged out. When given, it is revoked along with its family.
+    pub refresh_token: Option<String>,
 }
\ No newline at end of file